@@ -0,0 +1,5 @@
+DROP TABLE queue.requeue;
+
+ALTER TABLE queue.queue DROP COLUMN requeued_jobs;
+
+ALTER TABLE queue.jobs DROP COLUMN leased_until;
\ No newline at end of file
@@ -0,0 +1,13 @@
+ALTER TABLE queue.jobs ADD COLUMN leased_until timestamptz NOT NULL DEFAULT now();
+
+ALTER TABLE queue.queue ADD COLUMN requeued_jobs integer NOT NULL DEFAULT 0;
+
+CREATE TABLE IF NOT EXISTS queue.requeue (
+    render_id text    NOT NULL,
+    frame     integer NOT NULL,
+    slice     integer NOT NULL,
+
+    PRIMARY KEY (render_id, frame, slice)
+);
+
+ALTER TABLE queue.requeue ENABLE ROW LEVEL SECURITY;
\ No newline at end of file
@@ -0,0 +1,5 @@
+ALTER TABLE queue.requeue DROP COLUMN attempt;
+
+ALTER TABLE queue.jobs DROP COLUMN attempt;
+
+ALTER TABLE queue.queue DROP COLUMN max_attempts;
\ No newline at end of file
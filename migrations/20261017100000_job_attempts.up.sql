@@ -0,0 +1,5 @@
+ALTER TABLE queue.queue ADD COLUMN max_attempts integer NOT NULL DEFAULT 3;
+
+ALTER TABLE queue.jobs ADD COLUMN attempt integer NOT NULL DEFAULT 1;
+
+ALTER TABLE queue.requeue ADD COLUMN attempt integer NOT NULL DEFAULT 1;
\ No newline at end of file
@@ -0,0 +1 @@
+ALTER TABLE queue.queue DROP COLUMN priority;
\ No newline at end of file
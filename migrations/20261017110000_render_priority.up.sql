@@ -0,0 +1 @@
+ALTER TABLE queue.queue ADD COLUMN priority integer NOT NULL DEFAULT 0;
\ No newline at end of file
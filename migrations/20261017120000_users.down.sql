@@ -0,0 +1 @@
+DROP TABLE queue.users;
\ No newline at end of file
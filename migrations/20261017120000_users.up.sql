@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS queue.users (
+    user_id uuid    NOT NULL,
+
+    weight  integer NOT NULL DEFAULT 1,
+
+    PRIMARY KEY (user_id)
+);
+
+ALTER TABLE queue.users ENABLE ROW LEVEL SECURITY;
\ No newline at end of file
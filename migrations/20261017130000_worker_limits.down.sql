@@ -0,0 +1,3 @@
+DROP TABLE queue.subscriptions;
+
+ALTER TABLE queue.users DROP COLUMN max_workers;
\ No newline at end of file
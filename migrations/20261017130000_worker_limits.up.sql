@@ -0,0 +1,13 @@
+ALTER TABLE queue.users ADD COLUMN max_workers integer;
+
+CREATE TABLE IF NOT EXISTS queue.subscriptions (
+    subscription_item_id text    NOT NULL,
+
+    max_workers          integer,
+    -- Default priority of renders billed to the subscription item, for submissions without one.
+    priority             integer NOT NULL DEFAULT 0,
+
+    PRIMARY KEY (subscription_item_id)
+);
+
+ALTER TABLE queue.subscriptions ENABLE ROW LEVEL SECURITY;
\ No newline at end of file
@@ -0,0 +1,3 @@
+ALTER TABLE queue.queue DROP COLUMN popped_at;
+
+ALTER TABLE queue.queue DROP COLUMN submitted_at;
\ No newline at end of file
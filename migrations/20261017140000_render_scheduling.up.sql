@@ -0,0 +1,3 @@
+ALTER TABLE queue.queue ADD COLUMN submitted_at timestamptz NOT NULL DEFAULT now();
+
+ALTER TABLE queue.queue ADD COLUMN popped_at timestamptz;
\ No newline at end of file
@@ -0,0 +1 @@
+DROP TABLE IF EXISTS queue.outbox;
\ No newline at end of file
@@ -0,0 +1,9 @@
+-- Events are deleted once published.
+CREATE TABLE IF NOT EXISTS queue.outbox (
+    id bigserial PRIMARY KEY,
+    event jsonb NOT NULL,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    claimed_until timestamptz
+);
+
+ALTER TABLE queue.outbox ENABLE ROW LEVEL SECURITY;
\ No newline at end of file
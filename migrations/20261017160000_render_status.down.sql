@@ -0,0 +1,11 @@
+-- Before this migration a render only existed while it was queued.
+DELETE FROM queue.queue WHERE status IN ('complete', 'failed', 'canceled');
+
+DROP INDEX IF EXISTS queue.queue_status_idx;
+
+ALTER TABLE queue.queue DROP COLUMN canceled_at;
+ALTER TABLE queue.queue DROP COLUMN failed_at;
+ALTER TABLE queue.queue DROP COLUMN completed_at;
+ALTER TABLE queue.queue DROP COLUMN paused_at;
+ALTER TABLE queue.queue DROP COLUMN started_at;
+ALTER TABLE queue.queue DROP COLUMN status;
\ No newline at end of file
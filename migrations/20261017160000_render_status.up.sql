@@ -0,0 +1,12 @@
+ALTER TABLE queue.queue ADD COLUMN status text NOT NULL DEFAULT 'pending'
+    CHECK (status IN ('pending', 'running', 'paused', 'complete', 'failed', 'canceled'));
+
+ALTER TABLE queue.queue ADD COLUMN started_at timestamptz;
+ALTER TABLE queue.queue ADD COLUMN paused_at timestamptz;
+ALTER TABLE queue.queue ADD COLUMN completed_at timestamptz;
+ALTER TABLE queue.queue ADD COLUMN failed_at timestamptz;
+ALTER TABLE queue.queue ADD COLUMN canceled_at timestamptz;
+
+UPDATE queue.queue SET status = 'running', started_at = popped_at WHERE popped_at IS NOT NULL;
+
+CREATE INDEX IF NOT EXISTS queue_status_idx ON queue.queue (status);
\ No newline at end of file
@@ -0,0 +1,27 @@
+ALTER TABLE queue.queue ADD COLUMN frame_start integer;
+ALTER TABLE queue.queue ADD COLUMN frame_end integer;
+ALTER TABLE queue.queue ADD COLUMN step integer;
+ALTER TABLE queue.queue ADD COLUMN pointer_frame integer;
+
+-- Only a single range ("N", "N-M" or "N-MxS") can be expressed as start, end and step.
+DELETE FROM queue.queue WHERE frames !~ '^-?\d+(--?\d+)?(x\d+)?$';
+
+UPDATE queue.queue
+SET frame_start = m[1]::integer,
+    frame_end = coalesce(m[2], m[1])::integer,
+    step = coalesce(m[3], '1')::integer
+FROM (
+    SELECT id AS render_id, regexp_match(frames, '^(-?\d+)(?:-(-?\d+))?(?:x(\d+))?$') AS m
+    FROM queue.queue
+) AS parsed
+WHERE id = parsed.render_id;
+
+UPDATE queue.queue SET pointer_frame = frame_start + pointer_index * step;
+
+ALTER TABLE queue.queue ALTER COLUMN frame_start SET NOT NULL;
+ALTER TABLE queue.queue ALTER COLUMN frame_end SET NOT NULL;
+ALTER TABLE queue.queue ALTER COLUMN step SET NOT NULL;
+ALTER TABLE queue.queue ALTER COLUMN pointer_frame SET NOT NULL;
+
+ALTER TABLE queue.queue DROP COLUMN pointer_index;
+ALTER TABLE queue.queue DROP COLUMN frames;
\ No newline at end of file
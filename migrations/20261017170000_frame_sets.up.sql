@@ -0,0 +1,15 @@
+-- Frames become a frame set like "1-10,15,40-80x5", and the pointer the index of a frame in it.
+ALTER TABLE queue.queue ADD COLUMN frames text;
+ALTER TABLE queue.queue ADD COLUMN pointer_index integer;
+
+UPDATE queue.queue
+SET frames = frame_start || '-' || (frame_start + (frame_end - frame_start) / step * step) || 'x' || step,
+    pointer_index = (pointer_frame - frame_start) / step;
+
+ALTER TABLE queue.queue ALTER COLUMN frames SET NOT NULL;
+ALTER TABLE queue.queue ALTER COLUMN pointer_index SET NOT NULL;
+
+ALTER TABLE queue.queue DROP COLUMN pointer_frame;
+ALTER TABLE queue.queue DROP COLUMN step;
+ALTER TABLE queue.queue DROP COLUMN frame_end;
+ALTER TABLE queue.queue DROP COLUMN frame_start;
\ No newline at end of file
@@ -0,0 +1,4 @@
+-- The pointer of a preview-first render is a position in its order, not a frame index.
+DELETE FROM queue.queue WHERE frame_order <> 'ascending';
+
+ALTER TABLE queue.queue DROP COLUMN frame_order;
\ No newline at end of file
@@ -0,0 +1,2 @@
+ALTER TABLE queue.queue ADD COLUMN frame_order text NOT NULL DEFAULT 'ascending'
+    CHECK (frame_order IN ('ascending', 'preview-first'));
\ No newline at end of file
@@ -0,0 +1 @@
+DROP TABLE IF EXISTS queue.completed_slices;
\ No newline at end of file
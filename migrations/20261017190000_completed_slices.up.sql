@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS queue.completed_slices (
+    render_id text    NOT NULL,
+    frame     integer NOT NULL,
+    slice     integer NOT NULL,
+
+    PRIMARY KEY (render_id, frame, slice)
+);
+
+ALTER TABLE queue.completed_slices ENABLE ROW LEVEL SECURITY;
\ No newline at end of file
@@ -0,0 +1 @@
+ALTER TABLE queue.queue DROP COLUMN merge;
\ No newline at end of file
@@ -0,0 +1 @@
+ALTER TABLE queue.queue ADD COLUMN merge boolean NOT NULL DEFAULT false;
\ No newline at end of file
@@ -0,0 +1 @@
+DROP TABLE IF EXISTS queue.workers;
\ No newline at end of file
@@ -0,0 +1,15 @@
+CREATE TABLE IF NOT EXISTS queue.workers (
+    worker_id     text        NOT NULL,
+
+    first_seen_at timestamptz NOT NULL,
+    last_seen_at  timestamptz NOT NULL,
+    -- Workers that have never sent a heartbeat predate them, and are never lost.
+    heartbeat_at  timestamptz,
+    lost_at       timestamptz,
+
+    PRIMARY KEY (worker_id)
+);
+
+CREATE INDEX IF NOT EXISTS workers_alive_idx ON queue.workers (last_seen_at) WHERE lost_at IS NULL;
+
+ALTER TABLE queue.workers ENABLE ROW LEVEL SECURITY;
\ No newline at end of file
@@ -0,0 +1 @@
+ALTER TABLE queue.queue DROP COLUMN requirements;
\ No newline at end of file
@@ -0,0 +1,2 @@
+-- Renders submitted before requirements existed can run anywhere.
+ALTER TABLE queue.queue ADD COLUMN requirements jsonb NOT NULL DEFAULT '{}';
\ No newline at end of file
@@ -0,0 +1 @@
+ALTER TABLE queue.queue DROP COLUMN pool;
\ No newline at end of file
@@ -0,0 +1,2 @@
+-- Renders submitted before pools existed run on the default pool.
+ALTER TABLE queue.queue ADD COLUMN pool text NOT NULL DEFAULT 'default';
\ No newline at end of file
@@ -0,0 +1,6 @@
+DROP TABLE IF EXISTS queue.file_durations;
+
+ALTER TABLE queue.queue DROP COLUMN job_seconds;
+ALTER TABLE queue.queue DROP COLUMN timed_jobs;
+
+ALTER TABLE queue.jobs DROP COLUMN popped_at;
\ No newline at end of file
@@ -0,0 +1,19 @@
+-- Jobs popped before this are left untimed.
+ALTER TABLE queue.jobs ADD COLUMN popped_at timestamptz;
+
+ALTER TABLE queue.queue ADD COLUMN timed_jobs integer NOT NULL DEFAULT 0;
+ALTER TABLE queue.queue ADD COLUMN job_seconds double precision NOT NULL DEFAULT 0;
+
+-- Seconds per frame, across every render of the file version.
+CREATE TABLE IF NOT EXISTS queue.file_durations (
+    user_id      uuid             NOT NULL,
+    file_id      uuid             NOT NULL,
+    file_version integer          NOT NULL,
+
+    timed_jobs   integer          NOT NULL,
+    job_seconds  double precision NOT NULL,
+
+    PRIMARY KEY (file_id, file_version)
+);
+
+ALTER TABLE queue.file_durations ENABLE ROW LEVEL SECURITY;
\ No newline at end of file
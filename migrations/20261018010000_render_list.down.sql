@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS queue.queue_user_submitted_idx;
+DROP INDEX IF EXISTS queue.queue_submitted_idx;
\ No newline at end of file
@@ -0,0 +1,3 @@
+-- Pages of renders, oldest first.
+CREATE INDEX IF NOT EXISTS queue_submitted_idx ON queue.queue (submitted_at, id);
+CREATE INDEX IF NOT EXISTS queue_user_submitted_idx ON queue.queue (user_id, submitted_at, id);
\ No newline at end of file
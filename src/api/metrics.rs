@@ -0,0 +1,186 @@
+use anyhow::Result;
+use hyper::{
+    header::{HeaderValue, CONTENT_TYPE},
+    service::{make_service_fn, service_fn},
+    Body, Request, Response, Server, StatusCode,
+};
+use prometheus::{
+    Encoder, Histogram, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec, Opts,
+    Registry, TextEncoder,
+};
+use std::{convert::Infallible, future::Future, net::SocketAddr};
+use tracing::{error, info};
+
+#[derive(Clone)]
+pub struct Metrics {
+    registry: Registry,
+
+    // Queue depth, refreshed on every scrape
+    pub queued_renders: IntGaugeVec,
+    pub remaining_jobs: IntGaugeVec,
+    pub in_flight_jobs: IntGauge,
+
+    // Pop
+    pub pops: IntCounterVec,
+    pub pop_duration: Histogram,
+
+    // Events, by payload type
+    pub event_lag: HistogramVec,
+    pub event_duration: HistogramVec,
+    pub event_errors: IntCounterVec,
+}
+
+impl std::fmt::Debug for Metrics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Metrics").finish_non_exhaustive()
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Result<Self> {
+        let registry = Registry::new_custom(Some("queue".to_string()), None)?;
+
+        let queued_renders = IntGaugeVec::new(
+            Opts::new(
+                "renders",
+                "Active renders per user, for the users with the most jobs left",
+            ),
+            &["user_id"],
+        )?;
+        let remaining_jobs = IntGaugeVec::new(
+            Opts::new(
+                "remaining_jobs",
+                "Jobs of active renders not yet completed, per user, for the users with the most jobs left",
+            ),
+            &["user_id"],
+        )?;
+        let in_flight_jobs = IntGauge::new("in_flight_jobs", "Jobs popped and not yet reported")?;
+        let pops = IntCounterVec::new(
+            Opts::new(
+                "pops_total",
+                "Pop requests answered, by outcome (failed pops are only in pop_duration_seconds)",
+            ),
+            &["outcome"],
+        )?;
+        let pop_duration = Histogram::with_opts(HistogramOpts::new(
+            "pop_duration_seconds",
+            "Time taken to answer a pop request",
+        ))?;
+        let event_lag = HistogramVec::new(
+            HistogramOpts::new(
+                "event_lag_seconds",
+                "Time between an event being published and handled",
+            )
+            .buckets(vec![
+                0.01, 0.05, 0.1, 0.5, 1.0, 5.0, 10.0, 30.0, 60.0, 300.0,
+            ]),
+            &["payload"],
+        )?;
+        let event_duration = HistogramVec::new(
+            HistogramOpts::new("event_duration_seconds", "Time taken to handle an event"),
+            &["payload"],
+        )?;
+        let event_errors = IntCounterVec::new(
+            Opts::new(
+                "event_errors_total",
+                "Events whose handler returned an error",
+            ),
+            &["payload"],
+        )?;
+
+        registry.register(Box::new(queued_renders.clone()))?;
+        registry.register(Box::new(remaining_jobs.clone()))?;
+        registry.register(Box::new(in_flight_jobs.clone()))?;
+        registry.register(Box::new(pops.clone()))?;
+        registry.register(Box::new(pop_duration.clone()))?;
+        registry.register(Box::new(event_lag.clone()))?;
+        registry.register(Box::new(event_duration.clone()))?;
+        registry.register(Box::new(event_errors.clone()))?;
+
+        Ok(Self {
+            registry,
+            queued_renders,
+            remaining_jobs,
+            in_flight_jobs,
+            pops,
+            pop_duration,
+            event_lag,
+            event_duration,
+            event_errors,
+        })
+    }
+
+    fn encode(&self) -> Result<Vec<u8>> {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+
+        Ok(buffer)
+    }
+}
+
+// Serves the metrics on /metrics, calling `refresh` to update the queue depth gauges before each
+// scrape.
+pub async fn serve<F, Fut>(port: u16, metrics: Metrics, refresh: F) -> Result<()>
+where
+    F: Fn() -> Fut + Clone + Send + Sync + 'static,
+    Fut: Future<Output = Result<()>> + Send + 'static,
+{
+    let addr = SocketAddr::from(([0, 0, 0, 0], port));
+
+    let make_service = make_service_fn(move |_| {
+        let metrics = metrics.clone();
+        let refresh = refresh.clone();
+
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                handle(req, metrics.clone(), refresh.clone())
+            }))
+        }
+    });
+
+    info!("Serving metrics on {}", addr);
+
+    Server::try_bind(&addr)?.serve(make_service).await?;
+
+    Ok(())
+}
+
+async fn handle<F, Fut>(
+    req: Request<Body>,
+    metrics: Metrics,
+    refresh: F,
+) -> Result<Response<Body>, Infallible>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<()>>,
+{
+    if req.uri().path() != "/metrics" {
+        return Ok(status(StatusCode::NOT_FOUND));
+    }
+
+    // Serve the rest of the metrics even if the queue can't be read.
+    if let Err(e) = refresh().await {
+        error!("Failed to refresh metrics: {:?}", e);
+    }
+
+    match metrics.encode() {
+        Ok(buffer) => {
+            let mut resp = Response::new(Body::from(buffer));
+            resp.headers_mut().insert(
+                CONTENT_TYPE,
+                HeaderValue::from_static(prometheus::TEXT_FORMAT),
+            );
+            Ok(resp)
+        }
+        Err(e) => {
+            error!("Failed to encode metrics: {:?}", e);
+            Ok(status(StatusCode::INTERNAL_SERVER_ERROR))
+        }
+    }
+}
+
+fn status(code: StatusCode) -> Response<Body> {
+    let mut resp = Response::new(Body::empty());
+    *resp.status_mut() = code;
+    resp
+}
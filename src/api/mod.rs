@@ -1 +1,3 @@
+pub mod metrics;
+pub mod relay;
 pub mod service;
@@ -0,0 +1,86 @@
+use crate::domain::repository::OutboxRepository;
+use anyhow::Result;
+use chrono::Duration;
+use libcubr::event::event::EventTransport;
+use tracing::{error, info};
+
+// Events claimed per round trip to the outbox.
+const BATCH_SIZE: i64 = 100;
+
+// Publishes the events written to the outbox, oldest first. Events may still go out of order, e.g.
+// when several relays publish at once or a batch is retried, so consumers must not rely on it.
+#[derive(Clone, Debug)]
+pub struct OutboxRelay<OR, E>
+where
+    OR: OutboxRepository,
+    E: EventTransport,
+{
+    outbox: OR,
+    event: E,
+    // How long a claimed batch is held before another relay may publish it.
+    lease: Duration,
+}
+
+impl<OR, E> OutboxRelay<OR, E>
+where
+    OR: OutboxRepository,
+    E: EventTransport,
+{
+    pub fn new(outbox: OR, event: E, lease: Duration) -> Self {
+        Self {
+            outbox,
+            event,
+            lease,
+        }
+    }
+
+    // Publishes one batch and returns how many events went out. Stops at the first event that
+    // fails to publish, handing it and the rest of the batch back to be claimed again.
+    pub async fn relay(&self) -> Result<usize> {
+        let events = self.outbox.claim(BATCH_SIZE, self.lease).await?;
+
+        let mut sent = Vec::with_capacity(events.len());
+        let mut result = Ok(());
+
+        for (id, event) in &events {
+            if let Err(e) = self.event.publish(event).await {
+                result = Err(e);
+                break;
+            }
+            sent.push(*id);
+        }
+
+        if !sent.is_empty() {
+            self.outbox.delete(&sent).await?;
+        }
+
+        if result.is_err() {
+            let unsent = events[sent.len()..]
+                .iter()
+                .map(|(id, _)| *id)
+                .collect::<Vec<_>>();
+            self.outbox.release(&unsent).await?;
+        }
+
+        result.map(|_| sent.len())
+    }
+
+    pub async fn run(&self, period: std::time::Duration) {
+        let mut interval = tokio::time::interval(period);
+
+        loop {
+            interval.tick().await;
+
+            // Keep going while there is a backlog rather than waiting a period per batch.
+            loop {
+                match self.relay().await {
+                    Ok(sent) if sent as i64 == BATCH_SIZE => continue,
+                    Ok(0) => {}
+                    Ok(sent) => info!("Relayed {} events", sent),
+                    Err(e) => error!("Failed to relay events: {:?}", e),
+                }
+                break;
+            }
+        }
+    }
+}
@@ -1,261 +1,933 @@
+use crate::api::metrics::Metrics;
 use crate::domain::{
-    entity::Render,
-    load_balance,
-    repository::{JobRepository, RenderRepository},
+    capabilities::{Capabilities, Requirements},
+    entity::{Claim, Durations, InFlightJob, Release, Render, RenderStatus, Rerun, User},
+    frames::{FrameOrder, FrameSet},
+    limits::WorkerLimits,
+    load_balance::{self, LoadBalancer},
+    repository::{
+        JobRepository, OutboxRepository, RenderRepository, UserRepository, WorkerRepository,
+    },
+    scaling::{Hysteresis, ScalePolicy},
 };
-use anyhow::{anyhow, Result};
+use anyhow::Result;
+use chrono::{Duration, Utc};
 use libcubr::{event::event::*, rpc::rpc::ServiceResponse, service::queue::*};
-use tracing::info;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+use tracing::{error, info, warn};
+
+// Users labelled in the queue depth metrics, the rest are summed under "other".
+const METRICS_TOP_USERS: usize = 20;
+
+// Renders per ListRenders page, unless the request asks for fewer (or more, up to the max).
+const DEFAULT_LIST_LIMIT: i64 = 100;
+const MAX_LIST_LIMIT: i64 = 1000;
+
+#[derive(Clone, Debug)]
+pub struct QueueOptions {
+    // How long a popped job may run before it is handed to another worker, renewed by each of
+    // its worker's heartbeats.
+    pub lease: Duration,
+    // Attempts per (frame, slice) for renders that don't set their own limit.
+    pub max_attempts: i32,
+    // Worker pool for renders that don't name one.
+    pub default_pool: String,
+    // Share workers between users before sharing them between renders.
+    pub fair_share: bool,
+    // How long a worker may go without a heartbeat (or pop) before its jobs are released. Only
+    // applies to workers that send heartbeats.
+    pub worker_timeout: Duration,
+    pub scaling: ScalePolicy,
+}
 
 #[derive(Clone, Debug)]
-pub struct QueueServiceImpl<RR, JR, E>
+pub struct QueueServiceImpl<RR, JR, UR, WR, LB, OR>
 where
     RR: RenderRepository,
     JR: JobRepository,
-    E: EventTransport,
+    UR: UserRepository,
+    WR: WorkerRepository,
+    LB: LoadBalancer,
+    OR: OutboxRepository,
 {
     render: RR,
     job: JR,
-    event: E,
+    user: UR,
+    worker: WR,
+    balancer: LB,
+    outbox: OR,
+    options: QueueOptions,
+    metrics: Metrics,
+    // Held per instance, so instances behind the same RPC subject may answer with different
+    // targets for up to the scale-down delay.
+    hysteresis: Arc<Mutex<Hysteresis>>,
 }
 
-impl<RR, JR, E> QueueServiceImpl<RR, JR, E>
+impl<RR, JR, UR, WR, LB, OR> QueueServiceImpl<RR, JR, UR, WR, LB, OR>
 where
     RR: RenderRepository,
     JR: JobRepository,
-    E: EventTransport,
+    UR: UserRepository,
+    WR: WorkerRepository,
+    LB: LoadBalancer,
+    OR: OutboxRepository,
 {
-    pub fn new(render: RR, job: JR, event: E) -> Self {
-        Self { render, job, event }
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        render: RR,
+        job: JR,
+        user: UR,
+        worker: WR,
+        balancer: LB,
+        outbox: OR,
+        options: QueueOptions,
+        metrics: Metrics,
+    ) -> Self {
+        Self {
+            render,
+            job,
+            user,
+            worker,
+            balancer,
+            outbox,
+            options,
+            metrics,
+            hysteresis: Arc::new(Mutex::new(Hysteresis::default())),
+        }
+    }
+
+    pub async fn update_queue_metrics(&self) -> Result<()> {
+        let renders = self.render.load_queue().await?;
+        let in_flight = self.job.count_by_user().await?;
+
+        let mut users: HashMap<String, (i64, i64)> = HashMap::new();
+        for r in renders {
+            let (renders, jobs) = users.entry(r.user_id).or_default();
+            *renders += 1;
+            *jobs += i64::from(r.total_jobs - r.completed_jobs);
+        }
+
+        // Only the users with the most jobs left get a label of their own, so the number of series
+        // stays bounded however many users there are.
+        let mut users = users.into_iter().collect::<Vec<_>>();
+        users.sort_by(|(a, (_, a_jobs)), (b, (_, b_jobs))| b_jobs.cmp(a_jobs).then(a.cmp(b)));
+
+        // Reset first so users whose renders have all finished drop out.
+        self.metrics.queued_renders.reset();
+        self.metrics.remaining_jobs.reset();
+
+        for (i, (user_id, (renders, jobs))) in users.iter().enumerate() {
+            let label = if i < METRICS_TOP_USERS {
+                user_id.as_str()
+            } else {
+                "other"
+            };
+
+            self.metrics
+                .queued_renders
+                .with_label_values(&[label])
+                .add(*renders);
+            self.metrics
+                .remaining_jobs
+                .with_label_values(&[label])
+                .add(*jobs);
+        }
+
+        self.metrics.in_flight_jobs.set(in_flight.values().sum());
+
+        Ok(())
+    }
+
+    pub async fn release_expired_leases(&self) -> Result<()> {
+        let expired = self
+            .job
+            .release_expired(Utc::now(), |job, release| {
+                let mut events = vec![Event::new(Payload::JobLeaseExpired(JobLeaseExpired {
+                    user_id: job.user_id.clone(),
+                    render_id: job.render_id.clone(),
+                    frame: job.frame,
+                    slice: job.slice,
+                    worker_id: job.worker_id.clone(),
+                }))];
+                events.extend(released_events(job, release));
+                events
+            })
+            .await?;
+
+        for (job, release) in expired {
+            info!("Job lease expired ({:?}): {:?}", release, job);
+        }
+
+        Ok(())
+    }
+
+    async fn worker_limits(&self, users: &[User]) -> Result<WorkerLimits> {
+        let subscriptions = self.user.load_subscriptions().await?;
+
+        Ok(WorkerLimits {
+            users: users
+                .iter()
+                .filter_map(|u| u.max_workers.map(|max| (u.id.clone(), max.into())))
+                .collect(),
+            subscriptions: subscriptions
+                .into_iter()
+                .filter_map(|s| s.max_workers.map(|max| (s.item_id, max.into())))
+                .collect(),
+        })
+    }
+
+    // Workers wanted by each active or paused render, with the limits on how many jobs can run at
+    // once. Paused renders only want workers for the jobs they still have in flight.
+    async fn demand(&self) -> Result<(Vec<(Render, usize)>, WorkerLimits)> {
+        let mut renders = self.render.load_queue().await?;
+        renders.extend(self.render.load_paused().await?);
+        let users = self.user.load_all().await?;
+        let limits = self.worker_limits(&users).await?;
+
+        let ids = renders.iter().map(|r| r.id.clone()).collect::<Vec<_>>();
+        let mut in_flight: HashMap<String, usize> = HashMap::new();
+        for job in self.job.load_by_renders(&ids).await? {
+            *in_flight.entry(job.render_id).or_default() += 1;
+        }
+
+        let files = renders
+            .iter()
+            .map(|r| (r.file_id.clone(), r.file_version))
+            .collect::<Vec<_>>();
+        let files = self.render.file_durations(&files).await?;
+
+        let mut demand = Vec::with_capacity(renders.len());
+
+        for r in renders {
+            let poppable_jobs: usize = if r.status.is_active() {
+                r.poppable_jobs().try_into()?
+            } else {
+                0
+            };
+            let in_flight_jobs = in_flight.get(&r.id).copied().unwrap_or(0);
+            let file = files
+                .get(&(r.file_id.clone(), r.file_version))
+                .copied()
+                .unwrap_or_default();
+            let mean_job_seconds = r.mean_job_seconds(&file);
+            let workers =
+                self.options
+                    .scaling
+                    .demand(poppable_jobs, in_flight_jobs, mean_job_seconds);
+            info!(
+                r.id,
+                r.pool, poppable_jobs, in_flight_jobs, mean_job_seconds, workers, "Render demand"
+            );
+            demand.push((r, workers));
+        }
+
+        Ok((demand, limits))
+    }
+
+    // Holds `target` for scale-down hysteresis, then applies the policy's bounds.
+    fn bounded_target(&self, hysteresis: &mut Hysteresis, key: &str, target: usize) -> usize {
+        let scaling = &self.options.scaling;
+        let target = hysteresis.apply(key, target, Utc::now(), scaling.scale_down_delay);
+
+        scaling.clamp(target)
+    }
+
+    pub async fn release_lost_workers(&self) -> Result<()> {
+        let deadline = Utc::now() - self.options.worker_timeout;
+
+        let lost = self
+            .worker
+            .release_lost(deadline, released_events, |worker_id, jobs| {
+                vec![Event::new(Payload::WorkerLost(WorkerLost {
+                    worker_id: worker_id.to_string(),
+                    released_jobs: jobs.len() as i32,
+                }))]
+            })
+            .await?;
+
+        for (worker_id, jobs) in lost {
+            warn!("Worker lost with {} jobs: {:?}", jobs.len(), worker_id);
+        }
+
+        Ok(())
+    }
+
+    pub async fn run_reaper(&self, period: std::time::Duration) {
+        let mut interval = tokio::time::interval(period);
+
+        loop {
+            interval.tick().await;
+
+            if let Err(e) = self.release_expired_leases().await {
+                error!("Failed to release expired leases: {:?}", e);
+            }
+
+            if let Err(e) = self.release_lost_workers().await {
+                error!("Failed to release jobs of lost workers: {:?}", e);
+            }
+        }
     }
 }
 
 #[async_trait::async_trait]
-impl<RR, JR, E> QueueServiceRPC for QueueServiceImpl<RR, JR, E>
+impl<RR, JR, UR, WR, LB, OR> QueueServiceRPC for QueueServiceImpl<RR, JR, UR, WR, LB, OR>
 where
     RR: RenderRepository,
     JR: JobRepository,
-    E: EventTransport,
+    UR: UserRepository,
+    WR: WorkerRepository,
+    LB: LoadBalancer,
+    OR: OutboxRepository,
 {
     async fn pop(&self, req: PopRequest) -> Result<ServiceResponse<PopResponse, PopError>> {
+        // Observed when dropped, however the pop ends.
+        let _timer = self.metrics.pop_duration.start_timer();
+
         info!("Pop request: {:?}", req);
 
-        let queue = self.render.load_queue().await?;
+        // Popping shows the worker is alive as much as a heartbeat does.
+        self.worker.seen(&req.worker_id, Utc::now()).await?;
 
-        let mut render = match load_balance::select_render(queue).await {
-            Some(render) => render,
-            None => return Ok(ServiceResponse::Err(PopError::QueueEmpty)),
-        };
+        let mut queue = self.render.load_queue().await?;
 
-        if render.is_queue_drained() {
-            return Ok(ServiceResponse::Err(PopError::QueueEmpty));
+        // Workers that don't name a pool predate pools, and take jobs from any of them.
+        if let Some(pool) = &req.pool {
+            queue.retain(|r| &r.pool == pool);
         }
 
-        let job = match render.get_job(req.worker_id.clone()) {
-            Some(job) => job,
-            None => {
-                // This branch shouldn't happen.
-                self.render.delete(&render.id).await?;
-                return Err(anyhow!("Job pop out of range: {:?}", render));
-            }
+        // Workers that don't send capabilities predate them, and only get renders without
+        // requirements.
+        let capabilities = req
+            .capabilities
+            .as_ref()
+            .map(|capabilities| Capabilities {
+                tags: capabilities.tags.iter().cloned().collect(),
+                memory_gb: capabilities.memory_gb,
+            })
+            .unwrap_or_default();
+
+        queue.retain(|r| r.requirements.is_met_by(&capabilities));
+
+        // Users and their jobs in flight are only needed to share workers between users. Worker
+        // limits are checked by the claim.
+        let (weights, in_flight) = if self.options.fair_share {
+            let weights = self
+                .user
+                .load_all()
+                .await?
+                .into_iter()
+                .map(|u| (u.id, u.weight))
+                .collect::<HashMap<String, i32>>();
+
+            (weights, self.job.count_by_user().await?)
+        } else {
+            (HashMap::new(), HashMap::new())
         };
 
-        if render.is_first() {
-            self.event
-                .publish(&Event::new(Payload::RenderRunning(RenderRunning {
-                    id: render.id.clone(),
-                })))
-                .await?;
-        }
+        // Selection happens outside the claim, so another pop may drain the chosen render before
+        // we lock it, or take its owner to a worker limit. In that case drop it (or all of the
+        // owner's renders) from the candidates and select again.
+        let (render, job) = loop {
+            let selected = if self.options.fair_share {
+                load_balance::select_fair_share(&self.balancer, queue.clone(), &in_flight, &weights)
+                    .await
+            } else {
+                load_balance::select_render(&self.balancer, queue.clone()).await
+            };
 
-        render.advance_pointer();
+            let render = match selected {
+                Some(render) => render,
+                None => {
+                    self.metrics.pops.with_label_values(&["queue_empty"]).inc();
+                    return Ok(ServiceResponse::Err(PopError::QueueEmpty));
+                }
+            };
 
-        self.render.update_pointer(&render).await?;
+            let leased_until = Utc::now() + self.options.lease;
 
-        self.job.store(&job).await?;
+            match self
+                .render
+                .claim_job(&render.id, &req.worker_id, leased_until, |render, job| {
+                    let mut events = Vec::with_capacity(2);
 
-        self.event
-            .publish(&Event::new(Payload::JobRunning(JobRunning {
-                user_id: job.user_id.clone(),
-                frame: job.frame,
-                slice: job.slice,
-                render_id: render.id,
-                worker_id: req.worker_id,
-            })))
-            .await?;
+                    if render.status == RenderStatus::Pending {
+                        events.push(Event::new(Payload::RenderRunning(RenderRunning {
+                            id: render.id.clone(),
+                        })));
+                    }
+
+                    events.push(Event::new(Payload::JobRunning(JobRunning {
+                        user_id: job.user_id.clone(),
+                        frame: job.frame,
+                        slice: job.slice,
+                        render_id: job.render_id.clone(),
+                        worker_id: job.worker_id.clone(),
+                    })));
+
+                    events
+                })
+                .await?
+            {
+                Claim::Claimed(render, job) => break (*render, job),
+                Claim::Unavailable => queue.retain(|r| r.id != render.id),
+                Claim::UserSaturated => queue.retain(|r| r.user_id != render.user_id),
+                Claim::SubscriptionSaturated => {
+                    queue.retain(|r| r.subscription_item_id != render.subscription_item_id)
+                }
+            }
+        };
 
         let resp = PopResponse {
             user_id: job.user_id,
             render_id: job.render_id,
             frame: job.frame,
             slice: job.slice,
+            attempt: job.attempt,
+            kind: job.kind.as_str().to_string(),
             file_id: job.file_id,
             file_version: job.file_version,
             total_slices: job.total_slices,
             worker_id: job.worker_id,
             subscription_item_id: render.subscription_item_id,
+            leased_until: job.leased_until,
         };
 
         info!("Pop response: {:?}", resp);
+        self.metrics.pops.with_label_values(&["ok"]).inc();
         Ok(ServiceResponse::Ok(resp))
     }
 
     async fn get_scale_target(&self) -> Result<GetScaleTargetResponse> {
-        let renders = self.render.load_queue().await?;
+        let (demand, limits) = self.demand().await?;
 
-        let mut target: usize = 0;
+        // Jobs beyond an owner's worker limit can't run yet, so they don't need a worker.
+        let target = limits.cap(&demand);
+        let target = {
+            let mut hysteresis = self.hysteresis.lock().unwrap();
+            self.bounded_target(&mut hysteresis, "", target)
+        };
 
-        for r in renders {
-            let total_jobs: usize = r.total_jobs.try_into()?;
-            let completed_jobs: usize = r.completed_jobs.try_into()?;
-            let remaining_jobs = total_jobs - completed_jobs;
-            info!(
-                r.id,
-                total_jobs, completed_jobs, remaining_jobs, "Remaining jobs"
-            );
-            target += remaining_jobs;
+        // Only workers that meet a render's requirements can run it, so the target is also given
+        // per set of requirements for the scaler to pick instance types by. These are the
+        // current demand, capped by the limits but neither held nor bounded like the target.
+        let mut groups: HashMap<Requirements, Vec<(Render, usize)>> = HashMap::new();
+        for (render, workers) in demand {
+            groups
+                .entry(render.requirements.clone())
+                .or_default()
+                .push((render, workers));
         }
 
-        info!("GetScaleTarget response: {:?}", target);
+        let requirements = groups
+            .into_iter()
+            .map(|(requirements, demand)| RequirementsScaleTarget {
+                tags: requirements.tags.into_iter().collect(),
+                min_memory_gb: requirements.min_memory_gb,
+                target: limits.cap(&demand),
+            })
+            .filter(|r| r.target > 0)
+            .collect::<Vec<_>>();
+
+        info!("GetScaleTarget response: {:?} {:?}", target, requirements);
 
-        Ok(GetScaleTargetResponse { target })
+        Ok(GetScaleTargetResponse {
+            target,
+            requirements,
+        })
+    }
+
+    async fn get_pool_scale_targets(&self) -> Result<GetPoolScaleTargetsResponse> {
+        let (demand, limits) = self.demand().await?;
+
+        let mut pools: HashMap<String, Vec<(Render, usize)>> = HashMap::new();
+        for (render, workers) in demand {
+            pools
+                .entry(render.pool.clone())
+                .or_default()
+                .push((render, workers));
+        }
+
+        let targets = {
+            let mut hysteresis = self.hysteresis.lock().unwrap();
+
+            // Pools that have just emptied keep their held target until the delay is over. Pools
+            // without active renders or a held target are left out, their target is the minimum.
+            let keys = pools
+                .keys()
+                .chain(hysteresis.keys().filter(|k| !k.is_empty()))
+                .cloned()
+                .collect::<HashSet<String>>();
+
+            // An owner's limits apply in each pool rather than being split between them, as which
+            // pool its jobs run in isn't known until they are popped.
+            keys.into_iter()
+                .map(|pool| {
+                    let target = pools.get(&pool).map(|d| limits.cap(d)).unwrap_or(0);
+                    let target = self.bounded_target(&mut hysteresis, &pool, target);
+                    (pool, target)
+                })
+                .collect::<HashMap<String, usize>>()
+        };
+
+        info!("GetPoolScaleTargets response: {:?}", targets);
+
+        Ok(GetPoolScaleTargetsResponse { targets })
+    }
+
+    async fn get_render(
+        &self,
+        req: GetRenderRequest,
+    ) -> Result<ServiceResponse<RenderInfo, GetRenderError>> {
+        info!("GetRender request: {:?}", req);
+
+        let render = match self.render.load(&req.id).await? {
+            Some(render) => render,
+            None => return Ok(ServiceResponse::Err(GetRenderError::NotFound)),
+        };
+
+        let jobs = self
+            .job
+            .load_by_renders(std::slice::from_ref(&render.id))
+            .await?;
+        let file = self
+            .render
+            .file_durations(&[(render.file_id.clone(), render.file_version)])
+            .await?
+            .remove(&(render.file_id.clone(), render.file_version))
+            .unwrap_or_default();
+
+        Ok(ServiceResponse::Ok(render_info(render, jobs, &file)))
+    }
+
+    async fn list_renders(
+        &self,
+        req: ListRendersRequest,
+    ) -> Result<ServiceResponse<ListRendersResponse, ListRendersError>> {
+        info!("ListRenders request: {:?}", req);
+
+        let user_id = match req.user_id.as_deref().map(str::parse).transpose() {
+            Ok(user_id) => user_id,
+            Err(_) => return Ok(ServiceResponse::Err(ListRendersError::InvalidUserId)),
+        };
+        let limit = req.limit.map_or(DEFAULT_LIST_LIMIT, |limit| {
+            i64::from(limit).clamp(1, MAX_LIST_LIMIT)
+        });
+
+        let renders = self
+            .render
+            .list(user_id, req.after.as_deref(), limit)
+            .await?;
+
+        // A full page may have more after it.
+        let next = if renders.len() as i64 == limit {
+            renders.last().map(|r| r.id.clone())
+        } else {
+            None
+        };
+
+        let ids = renders.iter().map(|r| r.id.clone()).collect::<Vec<_>>();
+        let mut jobs: HashMap<String, Vec<InFlightJob>> = HashMap::new();
+        for job in self.job.load_by_renders(&ids).await? {
+            jobs.entry(job.render_id.clone()).or_default().push(job);
+        }
+
+        let files = renders
+            .iter()
+            .map(|r| (r.file_id.clone(), r.file_version))
+            .collect::<Vec<_>>();
+        let files = self.render.file_durations(&files).await?;
+
+        let renders = renders
+            .into_iter()
+            .map(|r| {
+                let in_flight = jobs.remove(&r.id).unwrap_or_default();
+                let file = files
+                    .get(&(r.file_id.clone(), r.file_version))
+                    .copied()
+                    .unwrap_or_default();
+                render_info(r, in_flight, &file)
+            })
+            .collect();
+
+        Ok(ServiceResponse::Ok(ListRendersResponse { renders, next }))
     }
 }
 
 #[async_trait::async_trait]
-impl<RR, JR, E> QueueServiceEvents for QueueServiceImpl<RR, JR, E>
+impl<RR, JR, UR, WR, LB, OR> QueueServiceEvents for QueueServiceImpl<RR, JR, UR, WR, LB, OR>
 where
     RR: RenderRepository,
     JR: JobRepository,
-    E: EventTransport,
+    UR: UserRepository,
+    WR: WorkerRepository,
+    LB: LoadBalancer,
+    OR: OutboxRepository,
 {
     async fn render_cancel_requested(&self, _: Header, event: RenderCancelRequested) -> Result<()> {
         info!("Render canceled: {:?}", event);
 
-        self.render.delete(&event.id).await?;
+        let deleted = self
+            .render
+            .cancel(&event.id, |render, jobs| {
+                // Workers stop early on these, and report back with JobCanceled.
+                let mut events = jobs
+                    .iter()
+                    .map(|job| {
+                        Event::new(Payload::JobCancelRequested(JobCancelRequested {
+                            user_id: job.user_id.clone(),
+                            render_id: job.render_id.clone(),
+                            frame: job.frame,
+                            slice: job.slice,
+                            worker_id: job.worker_id.clone(),
+                        }))
+                    })
+                    .collect::<Vec<_>>();
 
-        self.event
-            .publish(&Event::new(Payload::RenderCanceled(RenderCanceled {
-                id: event.id,
-            })))
+                events.push(Event::new(Payload::RenderCanceled(RenderCanceled {
+                    id: render.id.clone(),
+                    total_jobs: render.total_jobs,
+                    completed_jobs: render.completed_jobs,
+                    in_flight_jobs: jobs.len() as i32,
+                })));
+
+                events
+            })
             .await?;
 
+        match deleted {
+            Some((render, jobs)) => {
+                info!(
+                    "Render {:?} canceled with {} jobs in flight",
+                    render.id,
+                    jobs.len()
+                )
+            }
+            None => info!("Render not found or already finished: {:?}", event.id),
+        }
+
         Ok(())
     }
 
-    async fn render_submitted(&self, _: Header, event: RenderSubmitted) -> Result<()> {
-        info!("Render submitted: {:?}", event);
+    async fn render_pause_requested(&self, _: Header, event: RenderPauseRequested) -> Result<()> {
+        info!("Render pause requested: {:?}", event);
 
-        let render = Render::new(
-            event.user_id,
-            event.id.clone(),
-            event.file_id,
-            event.file_version,
-            event.frame_start,
-            event.frame_end,
-            event.step,
-            event.slices,
-            event.subscription_item_id,
-        );
-
-        self.render.store(&render).await?;
-
-        self.event
-            .publish(&Event::new(Payload::RenderPending(RenderPending {
-                id: event.id,
-            })))
+        let paused = self
+            .render
+            .pause(&event.id, |render| {
+                vec![Event::new(Payload::RenderPaused(RenderPaused {
+                    id: render.id.clone(),
+                }))]
+            })
             .await?;
 
+        if paused.is_none() {
+            info!("Render not found or not pausable: {:?}", event.id);
+        }
+
         Ok(())
     }
 
-    async fn job_canceled(&self, _: Header, event: JobCanceled) -> Result<()> {
-        info!("Job canceled: {:?}", event);
+    async fn render_resume_requested(&self, _: Header, event: RenderResumeRequested) -> Result<()> {
+        info!("Render resume requested: {:?}", event);
 
-        self.job
-            .delete(event.render_id, event.frame, event.slice)
+        let resumed = self
+            .render
+            .resume(&event.id, |render| {
+                vec![Event::new(Payload::RenderResumed(RenderResumed {
+                    id: render.id.clone(),
+                }))]
+            })
             .await?;
 
+        if resumed.is_none() {
+            info!("Render not found or not paused: {:?}", event.id);
+        }
+
         Ok(())
     }
 
-    async fn job_complete(&self, _: Header, event: JobComplete) -> Result<()> {
-        info!("Job complete: {:?}", event);
+    async fn render_submitted(&self, header: Header, event: RenderSubmitted) -> Result<()> {
+        info!("Render submitted: {:?}", event);
 
-        self.job
-            .delete(event.render_id.clone(), event.frame, event.slice)
-            .await?;
+        // A frame list takes precedence over the range.
+        let frames = match &event.frames {
+            Some(frames) => frames.parse(),
+            None => FrameSet::range(event.frame_start, event.frame_end, event.step),
+        };
 
-        let render = match self
-            .render
-            .increment_completed_jobs(&event.render_id)
-            .await?
-        {
+        let order = match &event.frame_order {
+            Some(order) => order.parse(),
+            None => Ok(FrameOrder::Ascending),
+        };
+
+        let requirements = Requirements::from_settings(&event.settings);
+
+        // Renders that don't set a priority take their subscription tier's.
+        let priority = match event.priority {
+            Some(priority) => priority,
+            None => self
+                .user
+                .load_subscriptions()
+                .await?
+                .into_iter()
+                .find(|s| s.item_id == event.subscription_item_id)
+                .map(|s| s.priority)
+                .unwrap_or_default(),
+        };
+
+        let render = match frames.and_then(|frames| {
+            Render::new(
+                event.user_id,
+                event.id.clone(),
+                event.file_id,
+                event.file_version,
+                frames,
+                order?,
+                event.slices,
+                event.merge.unwrap_or_default(),
+                event.subscription_item_id,
+                requirements?,
+                event.max_attempts.unwrap_or(self.options.max_attempts),
+                event
+                    .pool
+                    .unwrap_or_else(|| self.options.default_pool.clone()),
+                priority,
+                header.time,
+            )
+        }) {
+            Ok(render) => render,
+            Err(e) => {
+                warn!("Render rejected: {:?}", e);
+
+                self.outbox
+                    .store(&[Event::new(Payload::RenderRejected(RenderRejected {
+                        id: event.id,
+                        reason: format!("{:#}", e),
+                    }))])
+                    .await?;
+
+                return Ok(());
+            }
+        };
+
+        let pending = Event::new(Payload::RenderPending(RenderPending {
+            id: render.id.clone(),
+        }));
+
+        if !self.render.store(&render, &[pending]).await? {
+            info!("Render already submitted: {:?}", render.id);
+        }
+
+        Ok(())
+    }
+
+    async fn render_rerun_requested(&self, _: Header, event: RenderRerunRequested) -> Result<()> {
+        info!("Render rerun requested: {:?}", event);
+
+        let render = match self.render.load(&event.id).await? {
             Some(render) => render,
-            // This is the case where a render was canceled but a job kept going.
-            None => return Ok(()),
+            None => {
+                info!("Render not found: {:?}", event.id);
+                return Ok(());
+            }
+        };
+
+        let jobs = match event
+            .frames
+            .parse::<FrameSet>()
+            .and_then(|frames| render.rerun_jobs(&frames, event.slices.as_deref()))
+        {
+            Ok(jobs) => jobs,
+            Err(e) => {
+                warn!("Render rerun rejected: {:?}", e);
+
+                self.outbox
+                    .store(&[Event::new(Payload::RenderRerunRejected(
+                        RenderRerunRejected {
+                            id: event.id,
+                            reason: format!("{:#}", e),
+                        },
+                    ))])
+                    .await?;
+
+                return Ok(());
+            }
         };
 
-        // Check if the render is complete.
+        let rerun = self
+            .render
+            .rerun(&event.id, &jobs, |render, queued, canceled| {
+                // Merge jobs of the rerun frames, to run again once their slices complete
+                let mut events = canceled
+                    .iter()
+                    .map(|job| {
+                        Event::new(Payload::JobCancelRequested(JobCancelRequested {
+                            user_id: job.user_id.clone(),
+                            render_id: job.render_id.clone(),
+                            frame: job.frame,
+                            slice: job.slice,
+                            worker_id: job.worker_id.clone(),
+                        }))
+                    })
+                    .collect::<Vec<_>>();
+
+                events.push(Event::new(Payload::RenderRerunQueued(RenderRerunQueued {
+                    id: render.id.clone(),
+                    jobs: queued,
+                })));
+
+                // Only a finished render goes back to Pending, as jobs are only rerun once popped.
+                if queued > 0 && render.status == RenderStatus::Pending {
+                    events.push(Event::new(Payload::RenderPending(RenderPending {
+                        id: render.id.clone(),
+                    })));
+                }
 
-        let inprogress_jobs = self.job.count(event.render_id.clone()).await?;
+                events
+            })
+            .await?;
 
-        if inprogress_jobs == 0 && render.is_complete() {
-            self.render.delete(&event.render_id).await?;
+        match rerun {
+            Rerun::Queued(render, queued) => {
+                info!("Render rerun queued {} jobs: {:?}", queued, render.id)
+            }
+            Rerun::NotFound => info!("Render not found: {:?}", event.id),
+            Rerun::InFlight => {
+                info!(
+                    "Render rerun rejected, jobs still in flight: {:?}",
+                    event.id
+                );
 
-            self.event
-                .publish(&Event::new(Payload::RenderComplete(RenderComplete {
-                    id: event.render_id,
-                })))
-                .await?;
+                self.outbox
+                    .store(&[Event::new(Payload::RenderRerunRejected(
+                        RenderRerunRejected {
+                            id: event.id,
+                            reason: "Render has finished but still has jobs in flight".to_string(),
+                        },
+                    ))])
+                    .await?;
+            }
         }
 
         Ok(())
     }
 
-    async fn job_failed(&self, _: Header, event: JobFailed) -> Result<()> {
-        info!("Job failed: {:?}", event);
+    async fn worker_heartbeat(&self, _: Header, event: WorkerHeartbeat) -> Result<()> {
+        let now = Utc::now();
+
+        // A worker still sending heartbeats is still running its jobs, however long they take.
+        self.worker
+            .heartbeat(&event.worker_id, now, now + self.options.lease)
+            .await
+    }
 
-        self.job
-            .delete(event.render_id.clone(), event.frame, event.slice)
+    async fn job_canceled(&self, _: Header, event: JobCanceled) -> Result<()> {
+        info!("Job canceled: {:?}", event);
+
+        // Jobs canceled by a cancel or rerun are already gone, or held by another worker since.
+        // A job the worker gave up on by itself is handed back like a failed one.
+        let released = self
+            .job
+            .release(
+                &event.render_id,
+                event.frame,
+                event.slice,
+                &event.worker_id,
+                released_events,
+            )
             .await?;
 
-        let render = match self
+        if let Some((job, release)) = released {
+            info!("Canceled job released ({:?}): {:?}", release, job);
+        }
+
+        Ok(())
+    }
+
+    async fn job_complete(&self, _: Header, event: JobComplete) -> Result<()> {
+        info!("Job complete: {:?}", event);
+
+        let completed = self
             .render
-            .increment_completed_jobs(&event.render_id)
-            .await?
-        {
-            Some(render) => render,
-            // This is the case where a render was canceled or failed but a job kept going.
-            None => return Ok(()),
-        };
+            .complete_job(
+                &event.render_id,
+                event.frame,
+                event.slice,
+                &event.worker_id,
+                |render, completion| {
+                    let mut events = Vec::new();
 
-        // Check if the render is complete.
+                    if completion.frame_complete {
+                        events.push(Event::new(Payload::FrameComplete(FrameComplete {
+                            render_id: render.id.clone(),
+                            frame: event.frame,
+                        })));
+                    }
 
-        // If the render is a still (i.e. frame_start == frame_end) then emit RenderFailed.
+                    if render.status == RenderStatus::Complete {
+                        events.push(Event::new(Payload::RenderComplete(RenderComplete {
+                            id: render.id.clone(),
+                        })));
+                    } else {
+                        // Workers on the render, including the one that just completed this job,
+                        // are assumed to stay on it until it is done.
+                        let workers = usize::try_from(completion.in_flight_jobs + 1).unwrap_or(1);
 
-        if render.frame_start == render.frame_end {
-            self.render.delete(&event.render_id).await?;
+                        events.push(Event::new(Payload::RenderProgress(RenderProgress {
+                            id: render.id.clone(),
+                            completed_jobs: render.completed_jobs,
+                            total_jobs: render.total_jobs,
+                            eta: render.eta(&completion.file_durations, workers, Utc::now()),
+                        })));
+                    }
 
-            self.event
-                .publish(&Event::new(Payload::RenderFailed(RenderFailed {
-                    id: event.render_id,
-                })))
-                .await?;
+                    events
+                },
+            )
+            .await?;
 
-            return Ok(());
+        // None is the case where a render was canceled but a job kept going, the job's lease
+        // expired, or the event was redelivered.
+        if let Some((render, completion)) = completed {
+            if completion.frame_complete {
+                info!("Frame complete: {:?} {}", render.id, event.frame);
+            }
+            if render.status == RenderStatus::Complete {
+                info!("Render complete: {:?}", render.id);
+            }
         }
 
-        let inprogress_jobs = self.job.count(event.render_id.clone()).await?;
+        Ok(())
+    }
 
-        if inprogress_jobs == 0 && render.is_complete() {
-            self.render.delete(&event.render_id).await?;
+    async fn job_failed(&self, _: Header, event: JobFailed) -> Result<()> {
+        info!("Job failed: {:?}", event);
+
+        let (job, release) = match self
+            .job
+            .release(
+                &event.render_id,
+                event.frame,
+                event.slice,
+                &event.worker_id,
+                released_events,
+            )
+            .await?
+        {
+            Some(released) => released,
+            // This is the case where the job's lease expired, or the render was canceled or
+            // failed, but the job kept going. Also the case where the event was redelivered.
+            None => return Ok(()),
+        };
 
-            self.event
-                .publish(&Event::new(Payload::RenderComplete(RenderComplete {
-                    id: event.render_id,
-                })))
-                .await?;
+        match release {
+            Release::Requeued => info!("Job requeued after attempt {}: {:?}", job.attempt, job),
+            Release::RenderFailed => info!("Render failed: {:?}", job.render_id),
+            Release::Orphaned => {}
         }
 
         Ok(())
@@ -263,21 +935,109 @@ where
 }
 
 #[async_trait::async_trait]
-impl<RR, JR, E> EventRouter for QueueServiceImpl<RR, JR, E>
+impl<RR, JR, UR, WR, LB, OR> EventRouter for QueueServiceImpl<RR, JR, UR, WR, LB, OR>
 where
     RR: RenderRepository,
     JR: JobRepository,
-    E: EventTransport,
+    UR: UserRepository,
+    WR: WorkerRepository,
+    LB: LoadBalancer,
+    OR: OutboxRepository,
 {
     async fn route(&self, event: &Event) -> Result<()> {
         let header = event.header.clone();
-        match event.payload.clone() {
-            Payload::RenderSubmitted(e) => self.render_submitted(header, e).await,
-            Payload::RenderCancelRequested(e) => self.render_cancel_requested(header, e).await,
-            Payload::JobComplete(e) => self.job_complete(header, e).await,
-            Payload::JobFailed(e) => self.job_failed(header, e).await,
-            Payload::JobCanceled(e) => self.job_canceled(header, e).await,
-            _ => Ok(()),
+        let lag = Utc::now() - header.time;
+        let start = Instant::now();
+
+        let (payload, result) = match event.payload.clone() {
+            Payload::RenderSubmitted(e) => {
+                ("RenderSubmitted", self.render_submitted(header, e).await)
+            }
+            Payload::RenderCancelRequested(e) => (
+                "RenderCancelRequested",
+                self.render_cancel_requested(header, e).await,
+            ),
+            Payload::RenderPauseRequested(e) => (
+                "RenderPauseRequested",
+                self.render_pause_requested(header, e).await,
+            ),
+            Payload::RenderResumeRequested(e) => (
+                "RenderResumeRequested",
+                self.render_resume_requested(header, e).await,
+            ),
+            Payload::RenderRerunRequested(e) => (
+                "RenderRerunRequested",
+                self.render_rerun_requested(header, e).await,
+            ),
+            Payload::JobComplete(e) => ("JobComplete", self.job_complete(header, e).await),
+            Payload::JobFailed(e) => ("JobFailed", self.job_failed(header, e).await),
+            Payload::JobCanceled(e) => ("JobCanceled", self.job_canceled(header, e).await),
+            Payload::WorkerHeartbeat(e) => {
+                ("WorkerHeartbeat", self.worker_heartbeat(header, e).await)
+            }
+            _ => return Ok(()),
+        };
+
+        self.metrics
+            .event_duration
+            .with_label_values(&[payload])
+            .observe(start.elapsed().as_secs_f64());
+
+        // Negative if the publisher's clock is ahead of ours.
+        if let Ok(lag) = lag.to_std() {
+            self.metrics
+                .event_lag
+                .with_label_values(&[payload])
+                .observe(lag.as_secs_f64());
+        }
+
+        if result.is_err() {
+            self.metrics
+                .event_errors
+                .with_label_values(&[payload])
+                .inc();
         }
+
+        result
+    }
+}
+
+fn render_info(render: Render, jobs: Vec<InFlightJob>, file: &Durations) -> RenderInfo {
+    let pointer_frame = render.pointer_frame();
+    let mean_job_seconds = render.mean_job_seconds(file);
+    let eta = render.eta(file, jobs.len(), Utc::now());
+
+    RenderInfo {
+        user_id: render.user_id,
+        id: render.id,
+        status: render.status.as_str().to_string(),
+        pool: render.pool,
+        frames: render.frames.to_string(),
+        pointer_frame,
+        pointer_slice: render.pointer_slice,
+        total_jobs: render.total_jobs,
+        completed_jobs: render.completed_jobs,
+        mean_job_seconds,
+        eta,
+        in_flight_jobs: jobs
+            .into_iter()
+            .map(|job| InFlightJobInfo {
+                frame: job.frame,
+                slice: job.slice,
+                attempt: job.attempt,
+                worker_id: job.worker_id,
+                leased_until: job.leased_until,
+            })
+            .collect(),
+    }
+}
+
+// Events for a job whose worker let go of it without completing it.
+fn released_events(job: &InFlightJob, release: Release) -> Vec<Event> {
+    match release {
+        Release::RenderFailed => vec![Event::new(Payload::RenderFailed(RenderFailed {
+            id: job.render_id.clone(),
+        }))],
+        Release::Requeued | Release::Orphaned => vec![],
     }
 }
@@ -1,4 +1,6 @@
 use clap::Parser;
+
+use crate::domain::load_balance::Strategy;
 use tracing_subscriber::{fmt::format, prelude::__tracing_subscriber_field_MakeExt, EnvFilter};
 
 #[derive(Debug, Parser)]
@@ -9,6 +11,54 @@ pub struct Config {
     pub env: String,
     #[clap(default_value = "nats://localhost:4222", env)]
     pub nats_url: String,
+    // How long a popped job may run before it is handed to another worker. Each heartbeat from the
+    // worker renews it, but a worker that doesn't send heartbeats has to finish its jobs within it.
+    #[clap(default_value = "3600", env)]
+    pub job_lease_seconds: i64,
+    // How often expired leases and lost workers are looked for.
+    #[clap(default_value = "30", env)]
+    pub lease_reaper_interval_seconds: u64,
+    // How long a worker may go without a heartbeat (or pop) before its jobs are released. Workers
+    // that have never sent a heartbeat predate them, and are left to their job leases.
+    #[clap(default_value = "120", env)]
+    pub worker_heartbeat_timeout_seconds: i64,
+    // Attempts per (frame, slice) for renders submitted without their own limit.
+    #[clap(default_value = "3", env)]
+    pub max_job_attempts: i32,
+    // Worker pool for renders submitted without one.
+    #[clap(default_value = "default", env)]
+    pub default_pool: String,
+    // Share workers evenly between users (weighted by queue.users) before sharing them between
+    // each user's renders.
+    #[clap(long, env)]
+    pub fair_share: bool,
+    // One of random, fifo, round-robin or shortest-remaining-first.
+    #[clap(default_value = "random", env)]
+    pub load_balancer: Strategy,
+    // How often unsent events are published from the outbox.
+    #[clap(default_value = "200", env)]
+    pub outbox_relay_interval_millis: u64,
+    // How long a relay holds events it is publishing before another relay may take them over.
+    #[clap(default_value = "30", env)]
+    pub outbox_claim_seconds: i64,
+    // Bounds on the scale targets, per pool when targets are asked for per pool.
+    #[clap(default_value = "0", env)]
+    pub scale_target_min: usize,
+    #[clap(long, env)]
+    pub scale_target_max: Option<usize>,
+    // Workers a single render is worth scaling for. Unlimited if unset.
+    #[clap(long, env)]
+    pub scale_max_workers_per_render: Option<usize>,
+    // How long a scale target is held before it may come down.
+    #[clap(default_value = "300", env)]
+    pub scale_down_delay_seconds: i64,
+    // Scale for renders to finish their remaining jobs within this time, where job durations are
+    // known, rather than for one worker per job.
+    #[clap(long, env)]
+    pub scale_drain_seconds: Option<i64>,
+    // Port serving Prometheus metrics on /metrics.
+    #[clap(default_value = "9090", env)]
+    pub metrics_port: u16,
 }
 
 pub fn configure_tracing() {
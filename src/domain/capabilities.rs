@@ -0,0 +1,185 @@
+use std::collections::BTreeSet;
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+// What a worker can run, sent with each pop. Tags name what the worker has installed or attached,
+// e.g. "gpu" or "blender-3.4".
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Capabilities {
+    pub tags: BTreeSet<String>,
+    pub memory_gb: Option<i32>,
+}
+
+// What a render needs from a worker, derived from its submission settings.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Requirements {
+    pub tags: BTreeSet<String>,
+    pub min_memory_gb: Option<i32>,
+}
+
+impl Requirements {
+    // Reads the settings the queue knows about:
+    // - "blender_version": requires the "blender-<version>" tag
+    // - "device": "GPU" requires the "gpu" tag
+    // - "min_memory_gb": requires a worker with at least that much memory
+    // - "requirements": a list of further tags to require
+    // Anything else in the settings is left to the worker.
+    pub fn from_settings(settings: &Value) -> Result<Self> {
+        let mut requirements = Self::default();
+
+        match settings.get("blender_version") {
+            None | Some(Value::Null) => {}
+            Some(Value::String(version)) => {
+                requirements.tags.insert(format!("blender-{}", version));
+            }
+            Some(other) => bail!("Blender version is not a string: {}", other),
+        }
+
+        match settings.get("device") {
+            None | Some(Value::Null) => {}
+            Some(Value::String(device)) if device.eq_ignore_ascii_case("gpu") => {
+                requirements.tags.insert("gpu".to_string());
+            }
+            Some(Value::String(device)) if device.eq_ignore_ascii_case("cpu") => {}
+            Some(other) => bail!("Unknown device: {}", other),
+        }
+
+        match settings.get("min_memory_gb") {
+            None | Some(Value::Null) => {}
+            Some(value) => match value.as_i64().and_then(|gb| i32::try_from(gb).ok()) {
+                Some(gb) if gb >= 0 => requirements.min_memory_gb = Some(gb),
+                _ => bail!("Minimum memory is not a number of GB: {}", value),
+            },
+        }
+
+        match settings.get("requirements") {
+            None | Some(Value::Null) => {}
+            Some(Value::Array(tags)) => {
+                for tag in tags {
+                    match tag {
+                        Value::String(tag) => requirements.tags.insert(tag.clone()),
+                        other => bail!("Requirement is not a string: {}", other),
+                    };
+                }
+            }
+            Some(other) => bail!("Requirements are not a list: {}", other),
+        }
+
+        Ok(requirements)
+    }
+
+    pub fn is_met_by(&self, capabilities: &Capabilities) -> bool {
+        let memory = match (self.min_memory_gb, capabilities.memory_gb) {
+            (None, _) => true,
+            (Some(min), Some(memory)) => memory >= min,
+            (Some(_), None) => false,
+        };
+
+        memory && self.tags.is_subset(&capabilities.tags)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn tags(tags: &[&str]) -> BTreeSet<String> {
+        tags.iter().map(|tag| tag.to_string()).collect()
+    }
+
+    #[test]
+    fn requires_nothing_without_settings() {
+        for settings in [json!({}), json!(null), json!({ "samples": 128 })] {
+            assert_eq!(
+                Requirements::from_settings(&settings).unwrap(),
+                Requirements::default()
+            );
+        }
+    }
+
+    #[test]
+    fn reads_requirements_from_settings() {
+        let settings = json!({
+            "blender_version": "3.4",
+            "device": "GPU",
+            "min_memory_gb": 16,
+            "requirements": ["ocio"],
+        });
+
+        assert_eq!(
+            Requirements::from_settings(&settings).unwrap(),
+            Requirements {
+                tags: tags(&["blender-3.4", "gpu", "ocio"]),
+                min_memory_gb: Some(16),
+            }
+        );
+
+        let settings = json!({ "device": "cpu", "blender_version": null });
+        assert_eq!(
+            Requirements::from_settings(&settings).unwrap(),
+            Requirements::default()
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_settings() {
+        for settings in [
+            json!({ "blender_version": 3.4 }),
+            json!({ "device": "tpu" }),
+            json!({ "device": true }),
+            json!({ "min_memory_gb": -1 }),
+            json!({ "min_memory_gb": "16" }),
+            json!({ "min_memory_gb": 1.5 }),
+            json!({ "min_memory_gb": 1u64 << 40 }),
+            json!({ "requirements": "gpu" }),
+            json!({ "requirements": ["gpu", 1] }),
+        ] {
+            assert!(
+                Requirements::from_settings(&settings).is_err(),
+                "{}",
+                settings
+            );
+        }
+    }
+
+    #[test]
+    fn is_met_by_workers_with_the_tags_and_memory() {
+        let requirements = Requirements {
+            tags: tags(&["blender-3.4", "gpu"]),
+            min_memory_gb: Some(16),
+        };
+
+        let worker = |t: &[&str], memory_gb| Capabilities {
+            tags: tags(t),
+            memory_gb,
+        };
+
+        assert!(requirements.is_met_by(&worker(&["blender-3.4", "gpu", "ocio"], Some(32))));
+        assert!(requirements.is_met_by(&worker(&["blender-3.4", "gpu"], Some(16))));
+        assert!(!requirements.is_met_by(&worker(&["blender-3.4", "gpu"], Some(8))));
+        assert!(!requirements.is_met_by(&worker(&["blender-3.4", "gpu"], None)));
+        assert!(!requirements.is_met_by(&worker(&["blender-3.4"], Some(32))));
+    }
+
+    #[test]
+    fn gives_workers_without_capabilities_only_unrestricted_renders() {
+        let worker = Capabilities::default();
+
+        assert!(Requirements::default().is_met_by(&worker));
+        assert!(!Requirements {
+            tags: tags(&["gpu"]),
+            min_memory_gb: None,
+        }
+        .is_met_by(&worker));
+        assert!(!Requirements {
+            tags: BTreeSet::new(),
+            min_memory_gb: Some(0),
+        }
+        .is_met_by(&worker));
+    }
+}
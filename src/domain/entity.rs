@@ -1,4 +1,14 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Duration, Utc};
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::{
+    capabilities::Requirements,
+    frames::{FrameOrder, FrameSet, Position},
+};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Render {
@@ -11,21 +21,50 @@ pub struct Render {
     pub file_version: i32,
 
     // Range
-    pub frame_start: i32,
-    pub frame_end: i32,
-    pub step: i32,
+    pub frames: FrameSet,
+    pub order: FrameOrder,
     pub slices: i32,
 
-    // Pointer
-    pub pointer_frame: i32,
+    // Merge the slices of each frame with a follow-up job once they are all complete
+    pub merge: bool,
+
+    // Pointer, as the position of the frame in `order`
+    pub pointer_index: i32,
     pub pointer_slice: i32,
 
+    // Jobs handed back to the render (e.g. after a lease expired), popped before the pointer moves on
+    pub requeued_jobs: i32,
+
     // Completion status
     pub total_jobs: i32,
     pub completed_jobs: i32,
 
+    // How long the render's completed jobs took, for estimates
+    pub durations: Durations,
+
     // Billing
     pub subscription_item_id: String,
+
+    // What a worker needs to run the render's jobs
+    pub requirements: Requirements,
+
+    // Retry policy
+    pub max_attempts: i32,
+
+    // Scheduling
+    // The worker pool that runs the render's jobs, e.g. "preview" or "final"
+    pub pool: String,
+    pub priority: i32,
+    pub submitted_at: DateTime<Utc>,
+    pub popped_at: Option<DateTime<Utc>>,
+
+    // Lifecycle, with the time of each transition (Pending is `submitted_at`)
+    pub status: RenderStatus,
+    pub started_at: Option<DateTime<Utc>>,
+    pub paused_at: Option<DateTime<Utc>>,
+    pub completed_at: Option<DateTime<Utc>>,
+    pub failed_at: Option<DateTime<Utc>>,
+    pub canceled_at: Option<DateTime<Utc>>,
 }
 
 impl Render {
@@ -34,74 +73,381 @@ impl Render {
         id: String,
         file_id: String,
         file_version: i32,
-        frame_start: i32,
-        frame_end: i32,
-        step: i32,
+        frames: FrameSet,
+        order: FrameOrder,
         slices: i32,
+        merge: bool,
         subscription_item_id: String,
-    ) -> Self {
-        Self {
+        requirements: Requirements,
+        max_attempts: i32,
+        pool: String,
+        priority: i32,
+        submitted_at: DateTime<Utc>,
+    ) -> Result<Self> {
+        let mut render = Self {
             user_id,
             id,
             file_id,
             file_version,
-            frame_start,
-            frame_end,
-            step,
+            frames,
+            order,
             slices,
-            pointer_frame: frame_start,
+            // A single slice is already the whole frame.
+            merge: merge && slices > 1,
+            pointer_index: 0,
             pointer_slice: 0,
-            total_jobs: Self::total_jobs(frame_start, frame_end, step, slices),
+            requeued_jobs: 0,
+            total_jobs: 0,
             completed_jobs: 0,
+            durations: Durations::default(),
             subscription_item_id,
-        }
+            requirements,
+            max_attempts,
+            pool,
+            priority,
+            submitted_at,
+            popped_at: None,
+            status: RenderStatus::Pending,
+            started_at: None,
+            paused_at: None,
+            completed_at: None,
+            failed_at: None,
+            canceled_at: None,
+        };
+
+        render.validate()?;
+        render.total_jobs = (render.frames.len() * i64::from(render.jobs_per_frame())) as i32;
+
+        Ok(render)
     }
 
-    pub fn total_jobs(frame_start: i32, frame_end: i32, step: i32, slices: i32) -> i32 {
-        let frames = 1 + (frame_end - frame_start) / step;
+    // Rejects submissions that would break the pointer arithmetic or can't be stored.
+    fn validate(&self) -> Result<()> {
+        if self.id.is_empty() {
+            bail!("Render ID is empty");
+        }
+        if self.user_id.parse::<Uuid>().is_err() {
+            bail!("User ID is not a UUID: {:?}", self.user_id);
+        }
+        if self.file_id.parse::<Uuid>().is_err() {
+            bail!("File ID is not a UUID: {:?}", self.file_id);
+        }
+        if self.frames.is_empty() {
+            bail!("Render has no frames");
+        }
+        if self.slices < 1 {
+            bail!("Slices must be at least 1, got {}", self.slices);
+        }
+        if self.max_attempts < 1 {
+            bail!("Max attempts must be at least 1, got {}", self.max_attempts);
+        }
+        if self.pool.is_empty() {
+            bail!("Pool is empty");
+        }
+
+        let frames = self.frames.len();
+        if frames * i64::from(self.jobs_per_frame()) > i64::from(i32::MAX) {
+            bail!(
+                "Render has too many jobs: {} frames of {} slices",
+                frames,
+                self.slices
+            );
+        }
+        // Preview-first positions run up to twice the number of frames.
+        if self.order == FrameOrder::PreviewFirst && frames > i64::from(i32::MAX / 2) {
+            bail!("Render has too many frames to preview first: {}", frames);
+        }
 
-        frames * slices
+        Ok(())
     }
 
-    pub fn get_job(&self, worker_id: String) -> Option<Job> {
-        if self.is_queue_drained() {
-            return None;
+    // The slices, and the merge job if there is one.
+    fn jobs_per_frame(&self) -> i32 {
+        if self.merge {
+            self.slices + 1
+        } else {
+            self.slices
         }
+    }
 
-        Some(Job {
+    // A frame's merge job takes the slice after its last, so it is keyed like any other job.
+    pub fn merge_slice(&self) -> i32 {
+        self.slices
+    }
+
+    pub fn get_job(&self, worker_id: String, leased_until: DateTime<Utc>) -> Option<Job> {
+        let frame = self.pointer_frame()?;
+
+        Some(self.job(frame, self.pointer_slice, 1, worker_id, leased_until))
+    }
+
+    pub fn job(
+        &self,
+        frame: i32,
+        slice: i32,
+        attempt: i32,
+        worker_id: String,
+        leased_until: DateTime<Utc>,
+    ) -> Job {
+        Job {
             user_id: self.user_id.clone(),
             render_id: self.id.clone(),
-            frame: self.pointer_frame,
-            slice: self.pointer_slice,
+            frame,
+            slice,
+            attempt,
+            kind: if self.merge && slice == self.merge_slice() {
+                JobKind::Merge
+            } else {
+                JobKind::Render
+            },
 
             file_id: self.file_id.clone(),
             file_version: self.file_version,
             total_slices: self.slices,
             worker_id,
-        })
+            leased_until,
+        }
+    }
+
+    // The (frame, slice) jobs to run again for a rerun of `frames`, optionally only `slices` of
+    // them. Jobs the pointer hasn't reached yet are left out, as they are still to be popped.
+    pub fn rerun_jobs(&self, frames: &FrameSet, slices: Option<&[i32]>) -> Result<Vec<(i32, i32)>> {
+        if frames.len() > self.frames.len() {
+            bail!(
+                "Render {} has only {} frames, can't rerun {}",
+                self.id,
+                self.frames.len(),
+                frames.len()
+            );
+        }
+
+        let mut slices = match slices {
+            Some(slices) => slices.to_vec(),
+            None => (0..self.slices).collect(),
+        };
+        slices.sort_unstable();
+        slices.dedup();
+
+        if let Some(slice) = slices.iter().find(|s| !(0..self.slices).contains(*s)) {
+            bail!("Render {} has no slice {}", self.id, slice);
+        }
+
+        let pointer = (i64::from(self.pointer_index), self.pointer_slice);
+        let mut jobs = Vec::new();
+
+        for frame in frames.iter() {
+            let index = self
+                .frames
+                .index_of(frame)
+                .ok_or_else(|| anyhow!("Render {} has no frame {}", self.id, frame))?;
+            let position = self.order.position_of(self.frames.len(), index);
+
+            for &slice in &slices {
+                if (position, slice) < pointer {
+                    jobs.push((frame, slice));
+                }
+            }
+        }
+
+        Ok(jobs)
+    }
+
+    fn pointer_position(&self) -> Position {
+        self.order
+            .position(self.frames.len(), self.pointer_index.into())
+    }
+
+    // The frame under the pointer, or None once the pointer has passed the last frame.
+    pub fn pointer_frame(&self) -> Option<i32> {
+        match self.pointer_position() {
+            Position::Index(index) => self.frames.get(index),
+            Position::Empty | Position::End => None,
+        }
     }
 
-    // Returns true if the render is complete
     pub fn advance_pointer(&mut self) -> &mut Self {
         self.pointer_slice += 1;
         if self.pointer_slice >= self.slices {
             self.pointer_slice = 0;
-            self.pointer_frame += self.step;
+            self.pointer_index += 1;
+
+            // The pointer only ever rests on a frame, or the end.
+            while self.pointer_position() == Position::Empty {
+                self.pointer_index += 1;
+            }
         }
 
         self
     }
 
+    // Jobs that could be popped now: the requeued jobs and the slices under and after the pointer.
+    // Merge jobs only count once their slices are complete and they are requeued.
+    pub fn poppable_jobs(&self) -> i64 {
+        let frames = self
+            .order
+            .remaining(self.frames.len(), self.pointer_index.into());
+        let pointer = (frames * i64::from(self.slices) - i64::from(self.pointer_slice)).max(0);
+
+        i64::from(self.requeued_jobs) + pointer
+    }
+
+    // Moves the pointer past the last frame, so no more of the render's frames are popped.
+    pub fn drain_pointer(&mut self) -> &mut Self {
+        self.pointer_index = self.order.end(self.frames.len()) as i32;
+        self.pointer_slice = 0;
+
+        self
+    }
+
+    pub fn is_pointer_drained(&self) -> bool {
+        self.pointer_position() == Position::End
+    }
+
     pub fn is_queue_drained(&self) -> bool {
-        self.pointer_frame > self.frame_end
+        self.is_pointer_drained() && self.requeued_jobs == 0
     }
 
     pub fn is_complete(&self) -> bool {
         self.completed_jobs >= self.total_jobs
     }
 
-    pub fn is_first(&self) -> bool {
-        self.pointer_frame == self.frame_start && self.pointer_slice == 0
+    // Seconds per job, from the render's own completed jobs or, until it has some, from earlier
+    // renders of its file (see `Durations::per_frame`).
+    pub fn mean_job_seconds(&self, file: &Durations) -> Option<f64> {
+        self.durations
+            .mean()
+            .or_else(|| file.mean().map(|frame| frame / f64::from(self.slices)))
+    }
+
+    // When the remaining jobs should be done if `workers` keep running them. None for renders
+    // that aren't in the queue, or without any durations to go on.
+    pub fn eta(
+        &self,
+        file: &Durations,
+        workers: usize,
+        now: DateTime<Utc>,
+    ) -> Option<DateTime<Utc>> {
+        if !self.status.is_active() {
+            return None;
+        }
+
+        let remaining = f64::from((self.total_jobs - self.completed_jobs).max(0));
+        let seconds = remaining * self.mean_job_seconds(file)? / workers.max(1) as f64;
+
+        // No estimate rather than a nonsense one from corrupt durations.
+        if !seconds.is_finite() || seconds * 1000.0 >= i64::MAX as f64 {
+            return None;
+        }
+
+        now.checked_add_signed(Duration::milliseconds((seconds * 1000.0) as i64))
+    }
+
+    pub fn transition(&mut self, status: RenderStatus, at: DateTime<Utc>) -> Result<()> {
+        if !self.status.can_transition_to(status) {
+            bail!(
+                "Render {} can't go from {:?} to {:?}",
+                self.id,
+                self.status,
+                status
+            );
+        }
+
+        match status {
+            // A rerun render is no longer finished.
+            RenderStatus::Pending => {
+                self.completed_at = None;
+                self.failed_at = None;
+                self.canceled_at = None;
+            }
+            // A resumed render keeps the time it first started.
+            RenderStatus::Running => {
+                self.started_at.get_or_insert(at);
+            }
+            RenderStatus::Paused => self.paused_at = Some(at),
+            RenderStatus::Complete => self.completed_at = Some(at),
+            RenderStatus::Failed => self.failed_at = Some(at),
+            RenderStatus::Canceled => self.canceled_at = Some(at),
+        }
+
+        self.status = status;
+
+        Ok(())
+    }
+
+    // A resumed render goes back to Pending if it was paused before its first pop.
+    pub fn resume(&mut self, at: DateTime<Utc>) -> Result<()> {
+        let status = if self.started_at.is_some() {
+            RenderStatus::Running
+        } else {
+            RenderStatus::Pending
+        };
+
+        self.transition(status, at)
+    }
+}
+
+// Pending and Running renders are in the queue. Paused renders keep their place but aren't popped,
+// and the rest are finished unless some of their frames are rerun.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RenderStatus {
+    Pending,
+    Running,
+    Paused,
+    Complete,
+    Failed,
+    Canceled,
+}
+
+impl RenderStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Pending => "pending",
+            Self::Running => "running",
+            Self::Paused => "paused",
+            Self::Complete => "complete",
+            Self::Failed => "failed",
+            Self::Canceled => "canceled",
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        matches!(self, Self::Pending | Self::Running)
+    }
+
+    pub fn is_final(&self) -> bool {
+        matches!(self, Self::Complete | Self::Failed | Self::Canceled)
+    }
+
+    pub fn can_transition_to(&self, next: RenderStatus) -> bool {
+        use RenderStatus::*;
+
+        matches!(
+            (self, next),
+            (Pending, Running)
+                | (Pending | Running, Paused)
+                | (Paused, Pending | Running)
+                // A paused render's in-flight jobs may still finish it, or fail it.
+                | (Running | Paused, Complete | Failed)
+                | (Pending | Running | Paused, Canceled)
+                // Rerunning frames of a finished render puts it back in the queue.
+                | (Complete | Failed | Canceled, Pending)
+        )
+    }
+}
+
+impl FromStr for RenderStatus {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pending" => Ok(Self::Pending),
+            "running" => Ok(Self::Running),
+            "paused" => Ok(Self::Paused),
+            "complete" => Ok(Self::Complete),
+            "failed" => Ok(Self::Failed),
+            "canceled" => Ok(Self::Canceled),
+            _ => bail!("Unknown render status: {:?}", s),
+        }
     }
 }
 
@@ -112,10 +458,336 @@ pub struct Job {
     pub render_id: String,
     pub frame: i32,
     pub slice: i32,
+    pub attempt: i32,
+    pub kind: JobKind,
 
     // Metadata
     pub file_id: String,
     pub file_version: i32,
     pub total_slices: i32,
     pub worker_id: String,
+    pub leased_until: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum JobKind {
+    // Renders a slice of a frame
+    Render,
+    // Merges the rendered slices of a frame
+    Merge,
+}
+
+impl JobKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Render => "render",
+            Self::Merge => "merge",
+        }
+    }
+}
+
+// What happened to a job's (frame, slice) once its worker let go of it without completing it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Release {
+    // Handed back to the render for another attempt.
+    Requeued,
+    // Out of attempts, so the render failed.
+    RenderFailed,
+    // The render had already finished, e.g. canceled or failed by another job.
+    Orphaned,
+}
+
+// The outcome of claiming a render's next job.
+#[derive(Debug, Clone)]
+pub enum Claim {
+    // The render as it was before the claim, and the job
+    Claimed(Box<Render>, Job),
+    // The render is no longer active, or has been drained by another pop.
+    Unavailable,
+    // The render's user already has as many jobs in flight as it may.
+    UserSaturated,
+    // So has the render's subscription item.
+    SubscriptionSaturated,
+}
+
+// The outcome of rerunning some of a render's jobs.
+#[derive(Debug, Clone)]
+pub enum Rerun {
+    // The render after the rerun, and the number of jobs handed back
+    Queued(Box<Render>, i32),
+    // The render doesn't exist.
+    NotFound,
+    // The render has finished but still has jobs in flight, which report back to a finished render.
+    InFlight,
+}
+
+// A row of queue.jobs: a job that has been popped and not yet reported back.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InFlightJob {
+    pub user_id: String,
+    pub render_id: String,
+    pub frame: i32,
+    pub slice: i32,
+    pub attempt: i32,
+    pub worker_id: String,
+    pub leased_until: DateTime<Utc>,
+    // None for jobs popped before pop times were recorded
+    pub popped_at: Option<DateTime<Utc>>,
+}
+
+// Running totals of how long completed render jobs took, from pop to completion. Merge jobs
+// aren't counted, as they take a different time to the slices they merge.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Durations {
+    pub jobs: i32,
+    pub seconds: f64,
+}
+
+impl Durations {
+    pub fn add(&mut self, seconds: f64) -> &mut Self {
+        self.jobs += 1;
+        self.seconds += seconds;
+
+        self
+    }
+
+    pub fn mean(&self) -> Option<f64> {
+        if self.jobs > 0 {
+            Some(self.seconds / f64::from(self.jobs))
+        } else {
+            None
+        }
+    }
+
+    // A file's totals are kept per whole frame (each job's time times its render's slices), as
+    // renders of the same file may slice it differently.
+    pub fn per_frame(seconds: f64, slices: i32) -> f64 {
+        seconds * f64::from(slices)
+    }
+}
+
+// What completing a job did.
+#[derive(Debug, Clone)]
+pub struct Completion {
+    // The job completed the last slice of its frame
+    pub frame_complete: bool,
+    // The render's jobs still in flight
+    pub in_flight_jobs: i64,
+    // The render's file's totals, including the job
+    pub file_durations: Durations,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct User {
+    pub id: String,
+
+    // Fair share
+    pub weight: i32,
+
+    // Max jobs in flight at once, across all of the user's renders
+    pub max_workers: Option<i32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Subscription {
+    pub item_id: String,
+
+    // Max jobs in flight at once, across all renders billed to the subscription item
+    pub max_workers: Option<i32>,
+
+    // Priority of renders submitted without one, by subscription tier
+    pub priority: i32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(frames: &str, order: FrameOrder, slices: i32, merge: bool) -> Render {
+        Render::new(
+            Uuid::nil().to_string(),
+            "render".to_string(),
+            Uuid::nil().to_string(),
+            1,
+            frames.parse().unwrap(),
+            order,
+            slices,
+            merge,
+            "si_1".to_string(),
+            Requirements::default(),
+            3,
+            "default".to_string(),
+            0,
+            Utc::now(),
+        )
+        .unwrap()
+    }
+
+    fn pop(render: &mut Render) -> Job {
+        let job = render
+            .get_job("worker".to_string(), Utc::now())
+            .expect("job under the pointer");
+        render.advance_pointer();
+        job
+    }
+
+    #[test]
+    fn counts_every_slice_as_poppable_at_first() {
+        assert_eq!(
+            render("1-10", FrameOrder::Ascending, 1, false).poppable_jobs(),
+            10
+        );
+        assert_eq!(
+            render("1-10", FrameOrder::Ascending, 4, false).poppable_jobs(),
+            40
+        );
+        assert_eq!(
+            render("1-10", FrameOrder::PreviewFirst, 4, false).poppable_jobs(),
+            40
+        );
+    }
+
+    #[test]
+    fn counts_down_poppable_jobs_as_the_pointer_moves() {
+        for order in [FrameOrder::Ascending, FrameOrder::PreviewFirst] {
+            let mut render = render("1-7", order, 3, false);
+
+            for popped in 1..=21 {
+                pop(&mut render);
+                assert_eq!(render.poppable_jobs(), 21 - popped, "{:?}", order);
+            }
+
+            assert!(render.is_pointer_drained());
+            assert!(render.get_job("worker".to_string(), Utc::now()).is_none());
+        }
+    }
+
+    #[test]
+    fn counts_requeued_jobs_as_poppable() {
+        let mut render = render("1-4", FrameOrder::Ascending, 2, false);
+        for _ in 0..8 {
+            pop(&mut render);
+        }
+
+        render.requeued_jobs = 3;
+
+        assert_eq!(render.poppable_jobs(), 3);
+        assert!(!render.is_queue_drained());
+    }
+
+    #[test]
+    fn counts_merge_jobs_only_once_requeued() {
+        let mut render = render("1-4", FrameOrder::Ascending, 2, true);
+
+        assert_eq!(render.total_jobs, 12);
+        assert_eq!(render.poppable_jobs(), 8);
+
+        for _ in 0..8 {
+            pop(&mut render);
+        }
+        assert_eq!(render.poppable_jobs(), 0);
+
+        // The first frame's slices complete, and its merge is requeued
+        render.requeued_jobs += 1;
+        assert_eq!(render.poppable_jobs(), 1);
+    }
+
+    #[test]
+    fn reruns_only_jobs_behind_the_pointer() {
+        let mut render = render("1-5", FrameOrder::Ascending, 2, false);
+        // Frames 1 and 2, and the first slice of 3
+        for _ in 0..5 {
+            pop(&mut render);
+        }
+
+        let frames = "1-5".parse().unwrap();
+        assert_eq!(
+            render.rerun_jobs(&frames, None).unwrap(),
+            [(1, 0), (1, 1), (2, 0), (2, 1), (3, 0)]
+        );
+        assert_eq!(
+            render.rerun_jobs(&frames, Some(&[1, 1])).unwrap(),
+            [(1, 1), (2, 1)]
+        );
+    }
+
+    #[test]
+    fn reruns_jobs_behind_the_pointer_in_preview_order() {
+        let mut render = render("1-5", FrameOrder::PreviewFirst, 1, false);
+        // First, last and middle frames
+        for _ in 0..3 {
+            pop(&mut render);
+        }
+
+        let frames = "1-5".parse().unwrap();
+        assert_eq!(
+            render.rerun_jobs(&frames, None).unwrap(),
+            [(1, 0), (3, 0), (5, 0)]
+        );
+    }
+
+    #[test]
+    fn reruns_any_job_of_a_drained_render() {
+        let mut render = render("1-5", FrameOrder::PreviewFirst, 2, false);
+        pop(&mut render);
+        render.drain_pointer();
+
+        let frames = "2,4".parse().unwrap();
+        assert_eq!(
+            render.rerun_jobs(&frames, None).unwrap(),
+            [(2, 0), (2, 1), (4, 0), (4, 1)]
+        );
+    }
+
+    #[test]
+    fn rejects_reruns_of_frames_and_slices_not_in_the_render() {
+        let mut render = render("1-5", FrameOrder::Ascending, 2, false);
+        render.drain_pointer();
+
+        for frames in ["6", "0-2", "1-20"] {
+            let frames = frames.parse().unwrap();
+            assert!(render.rerun_jobs(&frames, None).is_err(), "{:?}", frames);
+        }
+
+        let frames = "1".parse().unwrap();
+        assert!(render.rerun_jobs(&frames, Some(&[2])).is_err());
+        assert!(render.rerun_jobs(&frames, Some(&[-1])).is_err());
+    }
+
+    #[test]
+    fn estimates_completion_from_durations() {
+        let render = render("1-10", FrameOrder::Ascending, 1, false);
+        let now = Utc::now();
+        let file = Durations {
+            jobs: 2,
+            seconds: 120.0,
+        };
+
+        assert_eq!(render.eta(&file, 2, now), Some(now + Duration::minutes(5)));
+        assert_eq!(render.eta(&Durations::default(), 2, now), None);
+    }
+
+    #[test]
+    fn gives_no_estimate_past_the_end_of_time() {
+        let render = render("1-10", FrameOrder::Ascending, 1, false);
+        let now = Utc::now();
+
+        for seconds in [1e13, 1e15, 1e300, f64::MAX, f64::INFINITY, f64::NAN] {
+            let file = Durations { jobs: 1, seconds };
+            assert_eq!(render.eta(&file, 1, now), None, "{}", seconds);
+        }
+    }
+
+    #[test]
+    fn drains_the_pointer_past_the_last_frame() {
+        for order in [FrameOrder::Ascending, FrameOrder::PreviewFirst] {
+            let mut render = render("1-10", order, 2, false);
+            pop(&mut render);
+
+            render.drain_pointer();
+
+            assert!(render.is_pointer_drained());
+            assert_eq!(render.poppable_jobs(), 0);
+        }
+    }
 }
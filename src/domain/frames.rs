@@ -0,0 +1,673 @@
+use std::{fmt, str::FromStr};
+
+use anyhow::{bail, Context, Error, Result};
+use serde::{Deserialize, Serialize};
+
+// The frames of a render, written like "1-10,15,40-80x5": single frames and inclusive ranges with
+// an optional step. Frames are addressed by their index in ascending order, so a render only needs
+// to store the set and an index into it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FrameSet {
+    // Sorted by start. Ranges may interleave (e.g. "1-9x2,2-10x2") but never share a frame.
+    ranges: Vec<FrameRange>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct FrameRange {
+    start: i32,
+    // The last frame of the range, always `start` plus a multiple of `step`
+    end: i32,
+    step: i32,
+}
+
+impl FrameRange {
+    fn new(start: i32, end: i32, step: i32) -> Result<Self> {
+        if end < start {
+            bail!("Frame end {} is before frame start {}", end, start);
+        }
+        if step < 1 {
+            bail!("Step must be at least 1, got {}", step);
+        }
+
+        let frames = (i64::from(end) - i64::from(start)) / i64::from(step);
+
+        Ok(Self {
+            start,
+            end: (i64::from(start) + frames * i64::from(step)) as i32,
+            step,
+        })
+    }
+
+    fn len(&self) -> i64 {
+        1 + (i64::from(self.end) - i64::from(self.start)) / i64::from(self.step)
+    }
+}
+
+impl FrameSet {
+    pub fn range(start: i32, end: i32, step: i32) -> Result<Self> {
+        Ok(Self {
+            ranges: vec![FrameRange::new(start, end, step)?],
+        })
+    }
+
+    pub fn len(&self) -> i64 {
+        self.ranges.iter().map(FrameRange::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = i32> + '_ {
+        // The ranges may interleave, so take the lowest next frame of any of them each time.
+        let mut next = self
+            .ranges
+            .iter()
+            .map(|r| Some(r.start))
+            .collect::<Vec<_>>();
+
+        std::iter::from_fn(move || {
+            let (i, frame) = next
+                .iter()
+                .enumerate()
+                .filter_map(|(i, frame)| frame.map(|frame| (i, frame)))
+                .min_by_key(|(_, frame)| *frame)?;
+
+            let range = &self.ranges[i];
+            next[i] = if frame < range.end {
+                Some(frame + range.step)
+            } else {
+                None
+            };
+
+            Some(frame)
+        })
+    }
+
+    // The number of frames up to and including `frame`.
+    fn count_to(&self, frame: i32) -> i64 {
+        self.ranges
+            .iter()
+            .filter(|r| r.start <= frame)
+            .map(|r| (i64::from(frame.min(r.end)) - i64::from(r.start)) / i64::from(r.step) + 1)
+            .sum()
+    }
+
+    // The index of `frame` in ascending order, if it is in the set.
+    pub fn index_of(&self, frame: i32) -> Option<i64> {
+        let contains = self.ranges.iter().any(|r| {
+            (r.start..=r.end).contains(&frame)
+                && (i64::from(frame) - i64::from(r.start)) % i64::from(r.step) == 0
+        });
+
+        if contains {
+            Some(self.count_to(frame) - 1)
+        } else {
+            None
+        }
+    }
+
+    // The frame at `index` in ascending order.
+    pub fn get(&self, index: i64) -> Option<i32> {
+        if index < 0 || index >= self.len() {
+            return None;
+        }
+
+        // The lowest frame with more than `index` frames up to it.
+        let mut lo = self.ranges.iter().map(|r| r.start).min()?;
+        let mut hi = self.ranges.iter().map(|r| r.end).max()?;
+        while lo < hi {
+            let mid = (i64::from(lo) + i64::from(hi)).div_euclid(2) as i32;
+            if self.count_to(mid) > index {
+                hi = mid;
+            } else {
+                lo = mid + 1;
+            }
+        }
+
+        Some(lo)
+    }
+}
+
+impl FromStr for FrameSet {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut ranges = s
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(|item| parse_range(item).with_context(|| format!("Invalid frames {:?}", item)))
+            .collect::<Result<Vec<_>>>()?;
+
+        if ranges.is_empty() {
+            bail!("No frames in {:?}", s);
+        }
+
+        ranges.sort_by_key(|r| (r.start, r.end, r.step));
+
+        // Ranges sharing a frame would hand it out twice. Ranges that only interleave are fine.
+        for (i, a) in ranges.iter().enumerate() {
+            for b in ranges[i + 1..].iter().take_while(|b| b.start <= a.end) {
+                if let Some(frame) = first_shared_frame(a, b) {
+                    bail!(
+                        "Frames {} and {} overlap at frame {}",
+                        display_range(a),
+                        display_range(b),
+                        frame
+                    );
+                }
+            }
+        }
+
+        Ok(Self { ranges })
+    }
+}
+
+// "N", "N-M" or "N-MxS". Frames may be negative, so the range's dash is the first one after the
+// start frame's digits.
+fn parse_range(item: &str) -> Result<FrameRange> {
+    let (range, step) = match item.split_once('x') {
+        Some((range, step)) => (range, step.trim().parse()?),
+        None => (item, 1),
+    };
+
+    let split = range
+        .char_indices()
+        .skip(1)
+        .find(|(_, c)| *c == '-')
+        .map(|(i, _)| i);
+
+    let (start, end) = match split {
+        Some(i) => (range[..i].trim().parse()?, range[i + 1..].trim().parse()?),
+        None => {
+            let frame = range.trim().parse()?;
+            (frame, frame)
+        }
+    };
+
+    FrameRange::new(start, end, step)
+}
+
+// The lowest frame in both ranges, found by solving start_a + i * step_a = start_b + j * step_b.
+fn first_shared_frame(a: &FrameRange, b: &FrameRange) -> Option<i32> {
+    let lo = i128::from(a.start.max(b.start));
+    let hi = i128::from(a.end.min(b.end));
+    if lo > hi {
+        return None;
+    }
+
+    let (step_a, step_b) = (i128::from(a.step), i128::from(b.step));
+    let (gcd, inverse, _) = extended_gcd(step_a, step_b);
+    let offset = i128::from(b.start) - i128::from(a.start);
+    if offset % gcd != 0 {
+        return None;
+    }
+
+    // The frames in both are `first` plus multiples of `lcm`.
+    let modulus = step_b / gcd;
+    let i = (offset / gcd * inverse).rem_euclid(modulus);
+    let first = i128::from(a.start) + i * step_a;
+    let lcm = step_a * modulus;
+
+    let frame = if first >= lo {
+        first
+    } else {
+        first + (lo - first + lcm - 1) / lcm * lcm
+    };
+
+    if frame <= hi {
+        Some(frame as i32)
+    } else {
+        None
+    }
+}
+
+// Returns (gcd, x, y) with a * x + b * y = gcd, for positive a and b.
+fn extended_gcd(a: i128, b: i128) -> (i128, i128, i128) {
+    if b == 0 {
+        (a, 1, 0)
+    } else {
+        let (gcd, x, y) = extended_gcd(b, a % b);
+        (gcd, y, x - a / b * y)
+    }
+}
+
+fn display_range(range: &FrameRange) -> String {
+    if range.start == range.end {
+        range.start.to_string()
+    } else if range.step == 1 {
+        format!("{}-{}", range.start, range.end)
+    } else {
+        format!("{}-{}x{}", range.start, range.end, range.step)
+    }
+}
+
+impl fmt::Display for FrameSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ranges = self.ranges.iter().map(display_range).collect::<Vec<_>>();
+
+        write!(f, "{}", ranges.join(","))
+    }
+}
+
+// The order in which a render's frames are popped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FrameOrder {
+    Ascending,
+    // First, last and middle frames, then the midpoints of each gap left, so the whole animation
+    // can be previewed early and fills in as it renders.
+    PreviewFirst,
+}
+
+// What is at a position of a frame order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Position {
+    // The index of a frame in the frame set
+    Index(i64),
+    // Nothing, move on to the next position
+    Empty,
+    // Past the last frame
+    End,
+}
+
+impl FrameOrder {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Ascending => "ascending",
+            Self::PreviewFirst => "preview-first",
+        }
+    }
+
+    // Maps a position of the order to a frame index, for a frame set of `len` frames. Positions
+    // are numbered from 0, so a render only has to store its current position.
+    pub fn position(&self, len: i64, position: i64) -> Position {
+        match self {
+            Self::Ascending if position < len => Position::Index(position),
+            Self::Ascending => Position::End,
+            Self::PreviewFirst => preview_first(len, position),
+        }
+    }
+
+    // The number of frames at `position` or after it.
+    pub fn remaining(&self, len: i64, position: i64) -> i64 {
+        match self {
+            Self::Ascending => (len - position.max(0)).max(0),
+            Self::PreviewFirst => len.max(0) - preview_first_visited(len, position),
+        }
+    }
+
+    // The first position past the last frame.
+    pub fn end(&self, len: i64) -> i64 {
+        match self {
+            Self::PreviewFirst if len > 2 => {
+                // The first level of the tree too deep for any gap to have a midpoint
+                let depth = 64 - i64::from((len - 2).leading_zeros());
+                (1 << depth) + 1
+            }
+            _ => len.max(0),
+        }
+    }
+
+    // The position of the frame at `index`, the inverse of `position`.
+    pub fn position_of(&self, len: i64, index: i64) -> i64 {
+        match self {
+            Self::Ascending => index,
+            Self::PreviewFirst => preview_first_position(len, index),
+        }
+    }
+}
+
+// Positions 0 and 1 are the first and last frames. The frames between them are split by their
+// midpoint at position 2, then the two halves by theirs at positions 3 and 4, and so on: level d
+// of this binary tree of gaps takes positions 2^d + 1 to 2^(d+1), and gaps too small to have a
+// midpoint leave their positions empty.
+fn preview_first(len: i64, position: i64) -> Position {
+    match position {
+        0 if len > 0 => return Position::Index(0),
+        1 if len > 1 => return Position::Index(len - 1),
+        0 | 1 => return Position::End,
+        _ => {}
+    }
+
+    let node = position - 1;
+    let depth = 63 - i64::from(node.leading_zeros());
+
+    // The widest gap at depth d is ceil((len - 1) / 2^d) frames apart, and needs to be at least
+    // 2 apart to have a midpoint.
+    if len - 1 <= 1 << depth {
+        return Position::End;
+    }
+
+    let (mut lo, mut hi) = (0, len - 1);
+    for bit in (0..depth).rev() {
+        if hi - lo < 2 {
+            return Position::Empty;
+        }
+
+        let mid = (lo + hi) / 2;
+        if node >> bit & 1 == 0 {
+            hi = mid;
+        } else {
+            lo = mid;
+        }
+    }
+
+    if hi - lo < 2 {
+        Position::Empty
+    } else {
+        Position::Index((lo + hi) / 2)
+    }
+}
+
+// The number of frames at positions before `position`, without walking them: the gaps of each
+// level are split as evenly as the levels above allow, so only the partly visited level needs a
+// walk down the tree.
+fn preview_first_visited(len: i64, position: i64) -> i64 {
+    let mut visited = len.clamp(0, 2).min(position.max(0));
+
+    let width = len - 1;
+    let mut depth = 0;
+    while depth < 62 && 1 << depth < width {
+        let nodes = (position - (1 << depth) - 1).clamp(0, 1 << depth);
+        visited += wide_gaps_before(width, depth, nodes);
+        depth += 1;
+    }
+
+    visited
+}
+
+// Of the 2^depth gaps `width` is split into at `depth`, the number among the first `nodes` that
+// are wide enough to have a midpoint.
+fn wide_gaps_before(mut width: i64, mut depth: u32, mut nodes: i64) -> i64 {
+    let mut count = 0;
+
+    while depth > 0 && nodes > 0 {
+        let half = 1 << (depth - 1);
+        depth -= 1;
+
+        if nodes <= half {
+            width /= 2;
+        } else {
+            count += wide_gaps(width / 2, depth);
+            nodes -= half;
+            width -= width / 2;
+        }
+    }
+
+    if nodes > 0 && width >= 2 {
+        count + 1
+    } else {
+        count
+    }
+}
+
+// Of the 2^depth gaps `width` is split into at `depth`, the number wide enough to have a midpoint.
+// Each is either width / 2^depth or one more, and the remainder are one more.
+fn wide_gaps(width: i64, depth: u32) -> i64 {
+    let gaps = 1 << depth;
+
+    match width / gaps {
+        0 => 0,
+        1 => width % gaps,
+        _ => gaps,
+    }
+}
+
+fn preview_first_position(len: i64, index: i64) -> i64 {
+    if index == 0 {
+        return 0;
+    }
+    if index == len - 1 {
+        return 1;
+    }
+
+    // Search the tree of gaps for the one whose midpoint is the frame.
+    let (mut lo, mut hi) = (0, len - 1);
+    let mut node = 1;
+    loop {
+        let mid = (lo + hi) / 2;
+        if index == mid {
+            return node + 1;
+        }
+
+        if index < mid {
+            hi = mid;
+            node *= 2;
+        } else {
+            lo = mid;
+            node = node * 2 + 1;
+        }
+    }
+}
+
+impl FromStr for FrameOrder {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ascending" => Ok(Self::Ascending),
+            "preview-first" => Ok(Self::PreviewFirst),
+            _ => bail!("Unknown frame order: {:?}", s),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frames(s: &str) -> Vec<i32> {
+        s.parse::<FrameSet>().unwrap().iter().collect()
+    }
+
+    #[test]
+    fn parses_frames_ranges_and_steps() {
+        assert_eq!(frames("3"), vec![3]);
+        assert_eq!(frames("1-4"), vec![1, 2, 3, 4]);
+        assert_eq!(frames("1-10x3"), vec![1, 4, 7, 10]);
+        assert_eq!(
+            frames("1-10,15,40-50x5"),
+            vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 15, 40, 45, 50]
+        );
+        assert_eq!(frames(" 5 , 1-2 ,"), vec![1, 2, 5]);
+    }
+
+    #[test]
+    fn parses_negative_frames() {
+        assert_eq!(frames("-3--1"), vec![-3, -2, -1]);
+        assert_eq!(frames("-2-2x2"), vec![-2, 0, 2]);
+        assert_eq!(frames("-5"), vec![-5]);
+    }
+
+    #[test]
+    fn ends_ranges_on_their_last_frame() {
+        let set: FrameSet = "1-10x4".parse().unwrap();
+
+        assert_eq!(set.to_string(), "1-9x4");
+        assert_eq!(set.len(), 3);
+    }
+
+    #[test]
+    fn rejects_invalid_frames() {
+        for s in ["", ",", "a", "1-", "10-1", "1-10x0", "1-10x-2", "1-10y2"] {
+            assert!(s.parse::<FrameSet>().is_err(), "{:?} parsed", s);
+        }
+    }
+
+    #[test]
+    fn rejects_frames_listed_twice() {
+        for s in [
+            "1-10,5",
+            "1-10,10-20",
+            "1-10x2,3",
+            "1-10x2,5-20x3",
+            "1-100x6,3-100x4",
+        ] {
+            assert!(s.parse::<FrameSet>().is_err(), "{:?} parsed", s);
+        }
+    }
+
+    #[test]
+    fn accepts_interleaved_ranges() {
+        assert_eq!(frames("1-10x2,2-10x2"), (1..=10).collect::<Vec<_>>());
+        assert_eq!(frames("1-10x2,4"), vec![1, 3, 4, 5, 7, 9]);
+        assert_eq!(frames("1-100x6,4-100x4").len(), 17 + 25);
+    }
+
+    #[test]
+    fn displays_parsed_frames() {
+        for s in ["1-10,15,40-80x5", "-3--1,1", "1-9x2,2-10x2", "1-9x2,4"] {
+            let set: FrameSet = s.parse().unwrap();
+
+            assert_eq!(set.to_string(), s);
+            assert_eq!(set.to_string().parse::<FrameSet>().unwrap(), set);
+        }
+    }
+
+    #[test]
+    fn indexes_frames_in_ascending_order() {
+        for s in [
+            "1-10,15,40-80x5",
+            "1-10x2,2-10x2",
+            "1-10x2,4",
+            "1-100x6,4-100x4",
+            "-7-7x7",
+        ] {
+            let set: FrameSet = s.parse().unwrap();
+            let frames = set.iter().collect::<Vec<_>>();
+
+            assert!(frames.windows(2).all(|w| w[0] < w[1]), "{:?}", s);
+            assert_eq!(frames.len() as i64, set.len());
+
+            for (index, frame) in frames.iter().enumerate() {
+                assert_eq!(set.get(index as i64), Some(*frame), "{:?}", s);
+                assert_eq!(set.index_of(*frame), Some(index as i64), "{:?}", s);
+            }
+
+            assert_eq!(set.get(-1), None);
+            assert_eq!(set.get(set.len()), None);
+        }
+    }
+
+    #[test]
+    fn finds_no_index_for_frames_outside_the_set() {
+        let set: FrameSet = "1-10x2,4,20".parse().unwrap();
+
+        for frame in [0, 2, 6, 10, 11, 19, 21] {
+            assert_eq!(set.index_of(frame), None, "{}", frame);
+        }
+    }
+
+    fn preview_first_indices(len: i64) -> Vec<i64> {
+        (0..)
+            .map(|p| FrameOrder::PreviewFirst.position(len, p))
+            .take_while(|p| *p != Position::End)
+            .filter_map(|p| match p {
+                Position::Index(index) => Some(index),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn orders_ascending_frames_by_index() {
+        let order = FrameOrder::Ascending;
+
+        for index in 0..5 {
+            assert_eq!(order.position(5, index), Position::Index(index));
+            assert_eq!(order.position_of(5, index), index);
+        }
+
+        assert_eq!(order.position(5, 5), Position::End);
+        assert_eq!(order.position(0, 0), Position::End);
+    }
+
+    #[test]
+    fn previews_first_last_and_middle_frames_first() {
+        assert_eq!(preview_first_indices(11)[..5], [0, 10, 5, 2, 7]);
+        assert_eq!(preview_first_indices(2), [0, 1]);
+        assert_eq!(preview_first_indices(1), [0]);
+        assert!(preview_first_indices(0).is_empty());
+    }
+
+    #[test]
+    fn previews_every_frame_once() {
+        for len in 0..70 {
+            let mut indices = preview_first_indices(len);
+            indices.sort_unstable();
+
+            assert_eq!(indices, (0..len).collect::<Vec<_>>(), "{}", len);
+        }
+    }
+
+    #[test]
+    fn finds_the_preview_position_of_every_frame() {
+        let order = FrameOrder::PreviewFirst;
+
+        for len in 1..70 {
+            for position in 0..4 * len {
+                match order.position(len, position) {
+                    Position::Index(index) => {
+                        assert_eq!(order.position_of(len, index), position, "{}", len)
+                    }
+                    Position::Empty => {}
+                    Position::End => break,
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn ends_after_the_last_frame() {
+        for order in [FrameOrder::Ascending, FrameOrder::PreviewFirst] {
+            for len in 0..70 {
+                let end = order.end(len);
+
+                assert_eq!(order.position(len, end), Position::End, "{}", len);
+                assert_ne!(order.position(len, end - 1), Position::End, "{}", len);
+            }
+        }
+    }
+
+    #[test]
+    fn counts_remaining_frames_at_every_position() {
+        for order in [FrameOrder::Ascending, FrameOrder::PreviewFirst] {
+            for len in 0..70 {
+                for position in 0..order.end(len) + 2 {
+                    let remaining = (position..order.end(len))
+                        .filter(|p| matches!(order.position(len, *p), Position::Index(_)))
+                        .count() as i64;
+
+                    assert_eq!(
+                        order.remaining(len, position),
+                        remaining,
+                        "{} {}",
+                        len,
+                        position
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn leaves_positions_of_small_gaps_empty() {
+        // 0 and 3, then 1 as the midpoint of 0-3, leaving no room in 0-1 before 2 fills in 1-3
+        let positions = (0..6)
+            .map(|p| FrameOrder::PreviewFirst.position(4, p))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            positions,
+            [
+                Position::Index(0),
+                Position::Index(3),
+                Position::Index(1),
+                Position::Empty,
+                Position::Index(2),
+                Position::End,
+            ]
+        );
+    }
+}
@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+
+use super::entity::Render;
+
+// Caps on how many jobs may be in flight at once, per user ID and per subscription item ID.
+// Owners without an entry are unlimited.
+#[derive(Debug, Clone, Default)]
+pub struct WorkerLimits {
+    pub users: HashMap<String, i64>,
+    pub subscriptions: HashMap<String, i64>,
+}
+
+impl WorkerLimits {
+    // Sums the jobs wanted by each render, counting no more jobs per subscription item and per
+    // user than the limits allow to run at once.
+    pub fn cap(&self, remaining: &[(Render, usize)]) -> usize {
+        let clamp = |jobs: usize, limit: Option<&i64>| match limit {
+            Some(limit) => jobs.min((*limit).max(0) as usize),
+            None => jobs,
+        };
+
+        let mut subscriptions: HashMap<(&str, &str), usize> = HashMap::new();
+        for (render, jobs) in remaining {
+            *subscriptions
+                .entry((&render.user_id, &render.subscription_item_id))
+                .or_default() += jobs;
+        }
+
+        let mut users: HashMap<&str, usize> = HashMap::new();
+        for ((user_id, subscription_item_id), jobs) in subscriptions {
+            *users.entry(user_id).or_default() +=
+                clamp(jobs, self.subscriptions.get(subscription_item_id));
+        }
+
+        users
+            .into_iter()
+            .map(|(user_id, jobs)| clamp(jobs, self.users.get(user_id)))
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::domain::{capabilities::Requirements, frames::FrameOrder};
+
+    fn user(n: u128) -> String {
+        Uuid::from_u128(n).to_string()
+    }
+
+    fn render(user_id: String, subscription_item_id: &str, jobs: usize) -> (Render, usize) {
+        let render = Render::new(
+            user_id,
+            "render".to_string(),
+            Uuid::nil().to_string(),
+            1,
+            "1-10".parse().unwrap(),
+            FrameOrder::Ascending,
+            1,
+            false,
+            subscription_item_id.to_string(),
+            Requirements::default(),
+            3,
+            "default".to_string(),
+            0,
+            Utc::now(),
+        )
+        .unwrap();
+
+        (render, jobs)
+    }
+
+    fn limits(users: &[(String, i64)], subscriptions: &[(&str, i64)]) -> WorkerLimits {
+        WorkerLimits {
+            users: users.iter().cloned().collect(),
+            subscriptions: subscriptions
+                .iter()
+                .map(|(id, max)| (id.to_string(), *max))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn sums_jobs_without_limits() {
+        let demand = [render(user(1), "si_1", 4), render(user(2), "si_2", 3)];
+
+        assert_eq!(WorkerLimits::default().cap(&demand), 7);
+        assert_eq!(WorkerLimits::default().cap(&[]), 0);
+    }
+
+    #[test]
+    fn caps_jobs_per_subscription_item_across_its_renders() {
+        let demand = [
+            render(user(1), "si_1", 2),
+            render(user(1), "si_1", 4),
+            render(user(2), "si_2", 3),
+        ];
+
+        assert_eq!(limits(&[], &[("si_1", 3)]).cap(&demand), 3 + 3);
+    }
+
+    #[test]
+    fn caps_a_users_subscription_items_together() {
+        let demand = [
+            render(user(1), "si_1", 6),
+            render(user(1), "si_2", 4),
+            render(user(2), "si_3", 3),
+        ];
+
+        // si_1 is capped at 3, and user 1's 3 + 4 at 5
+        let limits = limits(&[(user(1), 5)], &[("si_1", 3)]);
+        assert_eq!(limits.cap(&demand), 5 + 3);
+    }
+
+    #[test]
+    fn applies_the_lower_of_the_user_and_subscription_limits() {
+        let demand = [render(user(1), "si_1", 6)];
+
+        assert_eq!(limits(&[(user(1), 2)], &[("si_1", 4)]).cap(&demand), 2);
+        assert_eq!(limits(&[(user(1), 4)], &[("si_1", 2)]).cap(&demand), 2);
+    }
+
+    #[test]
+    fn counts_no_jobs_past_a_negative_limit() {
+        let demand = [render(user(1), "si_1", 6), render(user(2), "si_2", 1)];
+
+        assert_eq!(limits(&[(user(1), -1)], &[]).cap(&demand), 1);
+        assert_eq!(limits(&[], &[("si_2", -1)]).cap(&demand), 6);
+    }
+}
@@ -1,11 +1,176 @@
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+use anyhow::{anyhow, Error};
+
 use super::entity::Render;
 use rand::seq::SliceRandom;
 
-pub async fn select_render(renders: Vec<Render>) -> Option<Render> {
-    renders
+#[async_trait::async_trait]
+pub trait LoadBalancer: Clone + Send + Sync {
+    // Picks the render to pop next from a non-empty list of undrained renders of equal priority.
+    async fn select(&self, renders: Vec<Render>) -> Option<Render>;
+}
+
+#[derive(Clone, Debug)]
+pub struct Random;
+
+#[async_trait::async_trait]
+impl LoadBalancer for Random {
+    async fn select(&self, renders: Vec<Render>) -> Option<Render> {
+        renders.choose(&mut rand::thread_rng()).cloned()
+    }
+}
+
+// Oldest submission first.
+#[derive(Clone, Debug)]
+pub struct Fifo;
+
+#[async_trait::async_trait]
+impl LoadBalancer for Fifo {
+    async fn select(&self, renders: Vec<Render>) -> Option<Render> {
+        renders.into_iter().min_by_key(|r| r.submitted_at)
+    }
+}
+
+// The render popped least recently goes next, starting with renders that were never popped. The
+// pop time is stored on the render, so the rotation is shared by every queue instance.
+#[derive(Clone, Debug)]
+pub struct RoundRobin;
+
+#[async_trait::async_trait]
+impl LoadBalancer for RoundRobin {
+    async fn select(&self, renders: Vec<Render>) -> Option<Render> {
+        renders
+            .into_iter()
+            .min_by_key(|r| (r.popped_at, r.submitted_at))
+    }
+}
+
+// The render with the fewest jobs left to complete goes next.
+#[derive(Clone, Debug)]
+pub struct ShortestRemainingFirst;
+
+#[async_trait::async_trait]
+impl LoadBalancer for ShortestRemainingFirst {
+    async fn select(&self, renders: Vec<Render>) -> Option<Render> {
+        renders
+            .into_iter()
+            .min_by_key(|r| (r.total_jobs - r.completed_jobs, r.submitted_at))
+    }
+}
+
+// The built-in load balancers, selectable from Config.
+#[derive(Clone, Copy, Debug)]
+pub enum Strategy {
+    Random,
+    Fifo,
+    RoundRobin,
+    ShortestRemainingFirst,
+}
+
+impl FromStr for Strategy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "random" => Ok(Self::Random),
+            "fifo" => Ok(Self::Fifo),
+            "round-robin" => Ok(Self::RoundRobin),
+            "shortest-remaining-first" => Ok(Self::ShortestRemainingFirst),
+            _ => Err(anyhow!("Unknown load balancer strategy: {}", s)),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl LoadBalancer for Strategy {
+    async fn select(&self, renders: Vec<Render>) -> Option<Render> {
+        match self {
+            Self::Random => Random.select(renders).await,
+            Self::Fifo => Fifo.select(renders).await,
+            Self::RoundRobin => RoundRobin.select(renders).await,
+            Self::ShortestRemainingFirst => ShortestRemainingFirst.select(renders).await,
+        }
+    }
+}
+
+// Each step of priority doubles a render's chance of being picked, so urgent renders overtake
+// long low-priority ones without starving them.
+const MAX_PRIORITY: i32 = 10;
+
+fn weight(render: &Render) -> u32 {
+    1 << render.priority.clamp(0, MAX_PRIORITY)
+}
+
+// Picks a priority first, weighted by the renders that have it, then lets the balancer pick
+// between the renders of that priority.
+pub async fn select_render<LB: LoadBalancer>(
+    balancer: &LB,
+    renders: Vec<Render>,
+) -> Option<Render> {
+    let renders = renders
         .into_iter()
         .filter(|r| !r.is_queue_drained())
-        .collect::<Vec<Render>>()
+        .collect::<Vec<Render>>();
+
+    let priority = renders
+        .choose_weighted(&mut rand::thread_rng(), weight)
+        .ok()?
+        .priority;
+
+    balancer
+        .select(
+            renders
+                .into_iter()
+                .filter(|r| r.priority == priority)
+                .collect(),
+        )
+        .await
+}
+
+// Splits pops evenly between users first, in proportion to their weight, and between each user's
+// renders second: the user with the fewest in-flight jobs per unit of weight is served next. The
+// counts come from queue.jobs, so every queue instance agrees on the shares.
+pub async fn select_fair_share<LB: LoadBalancer>(
+    balancer: &LB,
+    renders: Vec<Render>,
+    in_flight: &HashMap<String, i64>,
+    weights: &HashMap<String, i32>,
+) -> Option<Render> {
+    let renders = renders
+        .into_iter()
+        .filter(|r| !r.is_queue_drained())
+        .collect::<Vec<Render>>();
+
+    let share = |user_id: &str| {
+        let jobs = in_flight.get(user_id).copied().unwrap_or(0) as f64;
+        let weight = weights.get(user_id).copied().unwrap_or(1).max(1) as f64;
+
+        jobs / weight
+    };
+
+    let min_share = renders
+        .iter()
+        .map(|r| share(&r.user_id))
+        .fold(f64::INFINITY, f64::min);
+
+    let user_id = renders
+        .iter()
+        .filter(|r| share(&r.user_id) <= min_share)
+        .map(|r| r.user_id.clone())
+        .collect::<HashSet<String>>()
+        .into_iter()
+        .collect::<Vec<String>>()
         .choose(&mut rand::thread_rng())
-        .cloned()
+        .cloned()?;
+
+    select_render(
+        balancer,
+        renders
+            .into_iter()
+            .filter(|r| r.user_id == user_id)
+            .collect(),
+    )
+    .await
 }
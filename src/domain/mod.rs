@@ -4,6 +4,10 @@
 
 // Store entities like Render, Job, Customer, etc. in the database with a repository contract.
 
+pub mod capabilities;
 pub mod entity;
+pub mod frames;
+pub mod limits;
 pub mod load_balance;
 pub mod repository;
+pub mod scaling;
@@ -1,27 +1,188 @@
+use std::collections::HashMap;
+
 use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use libcubr::event::event::Event;
+use uuid::Uuid;
+
+use super::entity::{
+    Claim, Completion, Durations, InFlightJob, Job, Release, Render, Rerun, Subscription, User,
+};
 
-use super::entity::{Job, Render};
+// State changes take the events they cause, or a closure building them from the outcome. The
+// events are written to the outbox in the same transaction as the change, and published from
+// there by the relay, so an event is never lost once its change is committed.
 
 #[async_trait::async_trait]
 pub trait RenderRepository: Clone + Send + Sync {
+    // Active (Pending or Running) renders only.
     async fn load_queue(&self) -> Result<Vec<Render>>;
 
+    // Paused renders, which may still have jobs in flight.
+    async fn load_paused(&self) -> Result<Vec<Render>>;
+
     async fn load(&self, id: &str) -> Result<Option<Render>>;
 
-    async fn store(&self, render: &Render) -> Result<()>;
+    // Up to `limit` renders in any status, oldest first, optionally only the given user's and only
+    // those after the render with ID `after`.
+    async fn list(
+        &self,
+        user_id: Option<Uuid>,
+        after: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<Render>>;
+
+    // Job durations per (file ID, file version), in seconds per frame. Files without completed jobs
+    // are left out.
+    async fn file_durations(
+        &self,
+        files: &[(String, i32)],
+    ) -> Result<HashMap<(String, i32), Durations>>;
+
+    // Returns false, leaving the stored render untouched and writing no events, if a render with
+    // the same ID exists.
+    async fn store(&self, render: &Render, outbox: &[Event]) -> Result<bool>;
+
+    // Atomically takes the next job of the render (a requeued job if there is one, otherwise the
+    // job under the pointer), advances the render and records the job as in progress with the
+    // given lease, moving a Pending render to Running. The owner's worker limits are checked under
+    // the same locks, so concurrent pops can't take them over their limits.
+    async fn claim_job<F>(
+        &self,
+        id: &str,
+        worker_id: &str,
+        leased_until: DateTime<Utc>,
+        outbox: F,
+    ) -> Result<Claim>
+    where
+        F: FnOnce(&Render, &Job) -> Vec<Event> + Send;
 
-    async fn update_pointer(&self, render: &Render) -> Result<()>;
+    // Atomically removes the worker's in-flight job and counts it as completed, adding the time
+    // since it was popped to the durations of the render and its file version, and moving the
+    // render to Complete once all of its jobs are. Returns the render and what the completion did,
+    // or None, counting nothing, if the worker doesn't hold the job (e.g. the completion was
+    // redelivered, the lease expired or the render was canceled).
+    async fn complete_job<F>(
+        &self,
+        id: &str,
+        frame: i32,
+        slice: i32,
+        worker_id: &str,
+        outbox: F,
+    ) -> Result<Option<(Render, Completion)>>
+    where
+        F: FnOnce(&Render, &Completion) -> Vec<Event> + Send;
 
-    async fn increment_completed_jobs(&self, id: &str) -> Result<Option<Render>>;
+    // Paused renders aren't popped, but their in-flight jobs carry on. Both return None, writing
+    // no events, if the render doesn't exist or can't be paused (or resumed) from its status.
+    async fn pause<F>(&self, id: &str, outbox: F) -> Result<Option<Render>>
+    where
+        F: FnOnce(&Render) -> Vec<Event> + Send;
 
-    async fn delete(&self, id: &str) -> Result<()>;
+    async fn resume<F>(&self, id: &str, outbox: F) -> Result<Option<Render>>
+    where
+        F: FnOnce(&Render) -> Vec<Event> + Send;
+
+    // Hands the (frame, slice) jobs back to the render to be popped again, skipping any that are in
+    // flight or already waiting, and puts the render back in the queue as Pending if it had
+    // finished. Merge jobs of the rerun frames that are waiting or in flight are canceled, and the
+    // outbox gets the in-flight ones. A finished render with jobs still in flight is left as it is,
+    // writing no events.
+    async fn rerun<F>(&self, id: &str, jobs: &[(i32, i32)], outbox: F) -> Result<Rerun>
+    where
+        F: FnOnce(&Render, i32, &[InFlightJob]) -> Vec<Event> + Send;
+
+    // Moves the render to Canceled, removes its in-flight and requeued jobs and drains its
+    // pointer. Returns the render and the jobs that were in flight, or None, writing no events, if
+    // the render doesn't exist or has already finished.
+    async fn cancel<F>(&self, id: &str, outbox: F) -> Result<Option<(Render, Vec<InFlightJob>)>>
+    where
+        F: FnOnce(&Render, &[InFlightJob]) -> Vec<Event> + Send;
 }
 
 #[async_trait::async_trait]
 pub trait JobRepository: Clone + Send + Sync {
-    async fn store(&self, job: &Job) -> Result<()>;
+    // In-flight jobs of the given renders.
+    async fn load_by_renders(&self, render_ids: &[String]) -> Result<Vec<InFlightJob>>;
+
+    // In-flight jobs per user ID.
+    async fn count_by_user(&self) -> Result<HashMap<String, i64>>;
+
+    // Atomically removes the worker's in-flight job and, if its render allows another attempt,
+    // hands the (frame, slice) back to the render so it is popped again. Otherwise the render
+    // moves to Failed. Returns None if the worker doesn't hold the job.
+    async fn release<F>(
+        &self,
+        render_id: &str,
+        frame: i32,
+        slice: i32,
+        worker_id: &str,
+        outbox: F,
+    ) -> Result<Option<(InFlightJob, Release)>>
+    where
+        F: FnOnce(&InFlightJob, Release) -> Vec<Event> + Send;
+
+    // Same as `release`, for every job whose lease ended before `now`. Each job is released on its
+    // own, and one that fails is logged and skipped rather than failing the others.
+    async fn release_expired<F>(
+        &self,
+        now: DateTime<Utc>,
+        outbox: F,
+    ) -> Result<Vec<(InFlightJob, Release)>>
+    where
+        F: Fn(&InFlightJob, Release) -> Vec<Event> + Send + Sync;
+}
+
+#[async_trait::async_trait]
+pub trait UserRepository: Clone + Send + Sync {
+    async fn load_all(&self) -> Result<Vec<User>>;
+
+    async fn load_subscriptions(&self) -> Result<Vec<Subscription>>;
+}
+
+#[async_trait::async_trait]
+pub trait WorkerRepository: Clone + Send + Sync {
+    // Registers the worker, or brings it back if it was lost.
+    async fn seen(&self, worker_id: &str, at: DateTime<Utc>) -> Result<()>;
+
+    // Same as `seen`, also recording that the worker sends heartbeats, and renews the leases of
+    // the worker's in-flight jobs until `leased_until`.
+    async fn heartbeat(
+        &self,
+        worker_id: &str,
+        at: DateTime<Utc>,
+        leased_until: DateTime<Utc>,
+    ) -> Result<()>;
+
+    // Releases the in-flight jobs of workers last seen before `deadline`, each on its own as
+    // `JobRepository::release` would with the `released` events, then marks the workers lost with
+    // the `lost` events. Workers that have never sent a heartbeat are left to their leases. Returns
+    // the workers with the jobs released for each.
+    async fn release_lost<R, L>(
+        &self,
+        deadline: DateTime<Utc>,
+        released: R,
+        lost: L,
+    ) -> Result<Vec<(String, Vec<(InFlightJob, Release)>)>>
+    where
+        R: Fn(&InFlightJob, Release) -> Vec<Event> + Send + Sync,
+        L: Fn(&str, &[(InFlightJob, Release)]) -> Vec<Event> + Send + Sync;
+}
+
+#[async_trait::async_trait]
+pub trait OutboxRepository: Clone + Send + Sync {
+    // For events that don't come with a state change.
+    async fn store(&self, events: &[Event]) -> Result<()>;
+
+    // Claims up to `limit` events, oldest first, for `lease`. Other relays skip claimed events
+    // until the claim runs out, so an event is only published twice if a relay dies between
+    // publishing and `delete`.
+    async fn claim(&self, limit: i64, lease: Duration) -> Result<Vec<(i64, Event)>>;
 
-    async fn delete(&self, render_id: String, frame: i32, slice: i32) -> Result<()>;
+    // Removes published events.
+    async fn delete(&self, ids: &[i64]) -> Result<()>;
 
-    async fn count(&self, render_id: String) -> Result<i64>;
+    // Gives up the claim on events that weren't published, so they can be claimed again straight
+    // away.
+    async fn release(&self, ids: &[i64]) -> Result<()>;
 }
@@ -0,0 +1,217 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Duration, Utc};
+
+// How scale targets are bounded.
+#[derive(Debug, Clone)]
+pub struct ScalePolicy {
+    // Workers to keep even with an empty queue.
+    pub min: usize,
+    pub max: Option<usize>,
+    // Workers a single render is worth scaling for, however many jobs it has left.
+    pub max_per_render: Option<usize>,
+    // How long a target is held before the fleet may shrink below it.
+    pub scale_down_delay: Duration,
+    // If set, renders with known job durations ask for enough workers to finish their jobs within
+    // this time, rather than one worker per job.
+    pub drain_time: Option<Duration>,
+}
+
+impl ScalePolicy {
+    // Workers wanted for a render with `poppable` jobs waiting and `in_flight` jobs running, each
+    // taking `mean_job_seconds` if known.
+    pub fn demand(
+        &self,
+        poppable: usize,
+        in_flight: usize,
+        mean_job_seconds: Option<f64>,
+    ) -> usize {
+        let jobs = poppable + in_flight;
+
+        let workers = match (self.drain_time, mean_job_seconds) {
+            (Some(drain_time), Some(seconds)) => {
+                let drain_seconds = (drain_time.num_milliseconds() as f64 / 1000.0).max(1.0);
+                let workers = (jobs as f64 * seconds / drain_seconds).ceil() as usize;
+
+                // At least one worker while there are jobs left, and no more than one per job.
+                workers.clamp(jobs.min(1), jobs)
+            }
+            _ => jobs,
+        };
+
+        match self.max_per_render {
+            Some(max) => workers.min(max),
+            None => workers,
+        }
+    }
+
+    pub fn clamp(&self, target: usize) -> usize {
+        let target = target.max(self.min);
+
+        match self.max {
+            Some(max) => target.min(max.max(self.min)),
+            None => target,
+        }
+    }
+}
+
+// Scale-down hysteresis. A target goes up straight away, but only comes down once it has stayed
+// below the held target for the whole delay, so a render finishing (or a gap between submissions)
+// doesn't shed workers that are wanted again a moment later.
+#[derive(Debug, Clone, Default)]
+pub struct Hysteresis {
+    // Per key, the held target and when it was last reached
+    held: HashMap<String, (usize, DateTime<Utc>)>,
+}
+
+impl Hysteresis {
+    pub fn apply(
+        &mut self,
+        key: &str,
+        target: usize,
+        now: DateTime<Utc>,
+        delay: Duration,
+    ) -> usize {
+        match self.held.get(key) {
+            Some(&(held, at)) if target < held && now - at < delay => held,
+            _ if target == 0 => {
+                self.held.remove(key);
+                0
+            }
+            _ => {
+                self.held.insert(key.to_string(), (target, now));
+                target
+            }
+        }
+    }
+
+    // Keys with a target held, e.g. pools that have just emptied.
+    pub fn keys(&self) -> impl Iterator<Item = &String> {
+        self.held.keys()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> ScalePolicy {
+        ScalePolicy {
+            min: 1,
+            max: Some(10),
+            max_per_render: None,
+            scale_down_delay: Duration::minutes(5),
+            drain_time: None,
+        }
+    }
+
+    #[test]
+    fn wants_a_worker_per_job_without_a_drain_time() {
+        assert_eq!(policy().demand(3, 2, Some(60.0)), 5);
+        assert_eq!(policy().demand(0, 0, None), 0);
+    }
+
+    #[test]
+    fn wants_enough_workers_to_drain_in_time() {
+        let policy = ScalePolicy {
+            drain_time: Some(Duration::minutes(5)),
+            ..policy()
+        };
+
+        // 10 jobs of a minute in 5 minutes
+        assert_eq!(policy.demand(8, 2, Some(60.0)), 2);
+        // Still one worker for short jobs, and no more than one per job for long ones
+        assert_eq!(policy.demand(1, 0, Some(1.0)), 1);
+        assert_eq!(policy.demand(3, 0, Some(3600.0)), 3);
+        // Without a known duration, a worker per job
+        assert_eq!(policy.demand(3, 0, None), 3);
+        assert_eq!(policy.demand(0, 0, Some(60.0)), 0);
+    }
+
+    #[test]
+    fn caps_workers_per_render() {
+        let policy = ScalePolicy {
+            max_per_render: Some(4),
+            ..policy()
+        };
+
+        assert_eq!(policy.demand(10, 0, None), 4);
+        assert_eq!(policy.demand(2, 1, None), 3);
+    }
+
+    #[test]
+    fn clamps_targets_to_the_bounds() {
+        assert_eq!(policy().clamp(0), 1);
+        assert_eq!(policy().clamp(5), 5);
+        assert_eq!(policy().clamp(20), 10);
+
+        let unbounded = ScalePolicy {
+            max: None,
+            ..policy()
+        };
+        assert_eq!(unbounded.clamp(20), 20);
+    }
+
+    #[test]
+    fn keeps_the_minimum_over_a_lower_maximum() {
+        let policy = ScalePolicy {
+            min: 5,
+            max: Some(2),
+            ..policy()
+        };
+
+        assert_eq!(policy.clamp(0), 5);
+        assert_eq!(policy.clamp(10), 5);
+    }
+
+    #[test]
+    fn scales_up_straight_away_and_down_after_the_delay() {
+        let mut hysteresis = Hysteresis::default();
+        let delay = Duration::minutes(5);
+        let start = Utc::now();
+
+        assert_eq!(hysteresis.apply("", 3, start, delay), 3);
+        assert_eq!(hysteresis.apply("", 8, start, delay), 8);
+
+        let later = start + delay - Duration::seconds(1);
+        assert_eq!(hysteresis.apply("", 2, later, delay), 8);
+        assert_eq!(hysteresis.apply("", 2, start + delay, delay), 2);
+    }
+
+    #[test]
+    fn holds_a_target_from_when_it_was_last_reached() {
+        let mut hysteresis = Hysteresis::default();
+        let delay = Duration::minutes(5);
+        let start = Utc::now();
+
+        hysteresis.apply("", 8, start, delay);
+        hysteresis.apply("", 8, start + Duration::minutes(3), delay);
+
+        assert_eq!(hysteresis.apply("", 2, start + delay, delay), 8);
+        assert_eq!(
+            hysteresis.apply("", 2, start + Duration::minutes(8), delay),
+            2
+        );
+    }
+
+    #[test]
+    fn forgets_keys_once_they_reach_zero() {
+        let mut hysteresis = Hysteresis::default();
+        let delay = Duration::minutes(5);
+        let start = Utc::now();
+
+        hysteresis.apply("a", 4, start, delay);
+        hysteresis.apply("b", 1, start, delay);
+
+        assert_eq!(
+            hysteresis.apply("a", 0, start + Duration::minutes(1), delay),
+            4
+        );
+        assert_eq!(hysteresis.apply("a", 0, start + delay, delay), 0);
+        assert_eq!(hysteresis.keys().collect::<Vec<_>>(), ["b"]);
+
+        // Nothing held, so zero stays zero
+        assert_eq!(hysteresis.apply("c", 0, start, delay), 0);
+        assert_eq!(hysteresis.keys().count(), 1);
+    }
+}
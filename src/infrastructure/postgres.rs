@@ -1,9 +1,24 @@
+use std::collections::HashMap;
+
 use anyhow::{Context, Result};
-use sqlx::{postgres::PgRow, types::Uuid, FromRow, PgPool, Row};
+use chrono::{DateTime, Duration, Utc};
+use libcubr::event::event::Event;
+use sqlx::{
+    postgres::{PgRow, Postgres},
+    types::{Json, Uuid},
+    FromRow, PgPool, Row, Transaction,
+};
+use tracing::error;
 
 use crate::domain::{
-    entity::{Job, Render},
-    repository::{JobRepository, RenderRepository},
+    capabilities::Requirements,
+    entity::{
+        Claim, Completion, Durations, InFlightJob, Job, Release, Render, RenderStatus, Rerun,
+        Subscription, User,
+    },
+    repository::{
+        JobRepository, OutboxRepository, RenderRepository, UserRepository, WorkerRepository,
+    },
 };
 
 #[derive(Clone, Debug)]
@@ -17,13 +32,58 @@ impl PgRenderRepository {
     }
 }
 
+impl PgRenderRepository {
+    // Applies `transition` to the locked render and stores the outcome. Returns None if the render
+    // doesn't exist or the transition is refused.
+    async fn transition<T, F>(&self, id: &str, transition: T, outbox: F) -> Result<Option<Render>>
+    where
+        T: FnOnce(&mut Render) -> Result<()>,
+        F: FnOnce(&Render) -> Vec<Event>,
+    {
+        let mut tx = self.pool.begin().await?;
+
+        let render: Option<Render> =
+            sqlx::query_as("SELECT * FROM queue.queue WHERE id = $1 FOR UPDATE")
+                .bind(id)
+                .fetch_optional(&mut tx)
+                .await?;
+
+        let mut render = match render {
+            Some(render) => render,
+            None => return Ok(None),
+        };
+
+        if transition(&mut render).is_err() {
+            return Ok(None);
+        }
+
+        update_status(&mut tx, &render).await?;
+        write_outbox(&mut tx, &outbox(&render)).await?;
+
+        tx.commit().await?;
+
+        Ok(Some(render))
+    }
+}
+
 #[async_trait::async_trait]
 impl RenderRepository for PgRenderRepository {
     async fn load_queue(&self) -> Result<Vec<Render>> {
-        let renders: Vec<Render> = sqlx::query_as("SELECT * FROM queue.queue")
-            .fetch_all(&self.pool)
-            .await
-            .context("RenderRepository::load_queue")?;
+        let renders: Vec<Render> =
+            sqlx::query_as("SELECT * FROM queue.queue WHERE status IN ('pending', 'running')")
+                .fetch_all(&self.pool)
+                .await
+                .context("RenderRepository::load_queue")?;
+
+        Ok(renders)
+    }
+
+    async fn load_paused(&self) -> Result<Vec<Render>> {
+        let renders: Vec<Render> =
+            sqlx::query_as("SELECT * FROM queue.queue WHERE status = 'paused'")
+                .fetch_all(&self.pool)
+                .await
+                .context("RenderRepository::load_paused")?;
 
         Ok(renders)
     }
@@ -38,69 +98,340 @@ impl RenderRepository for PgRenderRepository {
         Ok(render)
     }
 
-    async fn store(&self, render: &Render) -> Result<()> {
+    async fn list(
+        &self,
+        user_id: Option<Uuid>,
+        after: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<Render>> {
+        let renders: Vec<Render> = sqlx::query_as(
+            r#"
+            SELECT * FROM queue.queue
+            WHERE ($1::uuid IS NULL OR user_id = $1)
+            AND ($2::text IS NULL OR (submitted_at, id) > (
+                SELECT submitted_at, id FROM queue.queue WHERE id = $2
+            ))
+            ORDER BY submitted_at, id
+            LIMIT $3
+            "#,
+        )
+        .bind(&user_id)
+        .bind(after)
+        .bind(&limit)
+        .fetch_all(&self.pool)
+        .await
+        .context("RenderRepository::list")?;
+
+        Ok(renders)
+    }
+
+    async fn file_durations(
+        &self,
+        files: &[(String, i32)],
+    ) -> Result<HashMap<(String, i32), Durations>> {
+        let file_ids_uuid = files
+            .iter()
+            .map(|(id, _)| id.parse())
+            .collect::<Result<Vec<Uuid>, _>>()?;
+        let file_versions = files
+            .iter()
+            .map(|(_, version)| *version)
+            .collect::<Vec<_>>();
+
+        let durations: Vec<(Uuid, i32, i32, f64)> = sqlx::query_as(
+            r#"
+            SELECT file_id, file_version, timed_jobs, job_seconds FROM queue.file_durations
+            WHERE (file_id, file_version) IN (SELECT * FROM UNNEST($1::uuid[], $2::integer[]))
+            "#,
+        )
+        .bind(&file_ids_uuid)
+        .bind(&file_versions)
+        .fetch_all(&self.pool)
+        .await
+        .context("RenderRepository::file_durations")?;
+
+        Ok(durations
+            .into_iter()
+            .map(|(file_id, file_version, jobs, seconds)| {
+                (
+                    (file_id.to_string(), file_version),
+                    Durations { jobs, seconds },
+                )
+            })
+            .collect())
+    }
+
+    async fn store(&self, render: &Render, outbox: &[Event]) -> Result<bool> {
         let user_id_uuid: Uuid = render.user_id.parse()?;
         let file_id_uuid: Uuid = render.file_id.parse()?;
 
-        sqlx::query(
+        let mut tx = self.pool.begin().await.context("RenderRepository::store")?;
+
+        let result = sqlx::query(
             r#"
-            INSERT INTO queue.queue (id, user_id, file_id, file_version, frame_start, frame_end, step, slices, pointer_frame, pointer_slice, total_jobs, completed_jobs, subscription_item_id)
-            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)
-            ON CONFLICT (user_id, id) DO UPDATE SET
-                user_id = $2,
-                file_id = $3,
-                file_version = $4,
-                frame_start = $5,
-                frame_end = $6,
-                step = $7,
-                slices = $8,
-                pointer_frame = $9,
-                pointer_slice = $10,
-                total_jobs = $11,
-                completed_jobs = $12,
-                subscription_item_id = $13
+            INSERT INTO queue.queue (id, user_id, file_id, file_version, frames, frame_order, slices, merge, pointer_index, pointer_slice, total_jobs, completed_jobs, timed_jobs, job_seconds, subscription_item_id, requirements, requeued_jobs, max_attempts, pool, priority, submitted_at, popped_at, status)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20, $21, $22, $23)
+            ON CONFLICT (user_id, id) DO NOTHING
             "#,
         )
         .bind(&render.id)
         .bind(&user_id_uuid)
         .bind(&file_id_uuid)
         .bind(&render.file_version)
-        .bind(&render.frame_start)
-        .bind(&render.frame_end)
-        .bind(&render.step)
+        .bind(render.frames.to_string())
+        .bind(render.order.as_str())
         .bind(&render.slices)
-        .bind(&render.pointer_frame)
+        .bind(&render.merge)
+        .bind(&render.pointer_index)
         .bind(&render.pointer_slice)
         .bind(&render.total_jobs)
         .bind(&render.completed_jobs)
+        .bind(&render.durations.jobs)
+        .bind(&render.durations.seconds)
         .bind(&render.subscription_item_id)
-        .execute(&self.pool)
+        .bind(Json(&render.requirements))
+        .bind(&render.requeued_jobs)
+        .bind(&render.max_attempts)
+        .bind(&render.pool)
+        .bind(&render.priority)
+        .bind(&render.submitted_at)
+        .bind(&render.popped_at)
+        .bind(render.status.as_str())
+        .execute(&mut tx)
         .await
         .context("RenderRepository::store")?;
 
-        Ok(())
+        if result.rows_affected() == 0 {
+            return Ok(false);
+        }
+
+        write_outbox(&mut tx, outbox)
+            .await
+            .context("RenderRepository::store")?;
+
+        tx.commit().await.context("RenderRepository::store")?;
+
+        Ok(true)
     }
 
-    async fn update_pointer(&self, render: &Render) -> Result<()> {
+    async fn claim_job<F>(
+        &self,
+        id: &str,
+        worker_id: &str,
+        leased_until: DateTime<Utc>,
+        outbox: F,
+    ) -> Result<Claim>
+    where
+        F: FnOnce(&Render, &Job) -> Vec<Event> + Send,
+    {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .context("RenderRepository::claim_job")?;
+
+        // The row lock is held until commit, so concurrent pops on the same render (from this or
+        // any other queue instance) are serialised and each see the pointer left by the last one.
+        let render: Option<Render> =
+            sqlx::query_as("SELECT * FROM queue.queue WHERE id = $1 FOR UPDATE")
+                .bind(id)
+                .fetch_optional(&mut tx)
+                .await
+                .context("RenderRepository::claim_job")?;
+
+        let mut render = match render {
+            Some(render) if render.status.is_active() => render,
+            _ => return Ok(Claim::Unavailable),
+        };
+
+        // The owner's limit rows are locked too (after the render, as every claim does), so pops
+        // for the same owner on other renders wait for this one before counting its jobs.
+        let user_id_uuid: Uuid = render.user_id.parse()?;
+
+        let user_limit: Option<(i32,)> = sqlx::query_as(
+            "SELECT max_workers FROM queue.users WHERE user_id = $1 AND max_workers IS NOT NULL FOR UPDATE",
+        )
+        .bind(&user_id_uuid)
+        .fetch_optional(&mut tx)
+        .await
+        .context("RenderRepository::claim_job")?;
+
+        if let Some((max_workers,)) = user_limit {
+            let (in_flight,): (i64,) =
+                sqlx::query_as("SELECT COUNT(*) FROM queue.jobs WHERE user_id = $1")
+                    .bind(&user_id_uuid)
+                    .fetch_one(&mut tx)
+                    .await
+                    .context("RenderRepository::claim_job")?;
+
+            if in_flight >= i64::from(max_workers) {
+                return Ok(Claim::UserSaturated);
+            }
+        }
+
+        let subscription_limit: Option<(i32,)> = sqlx::query_as(
+            r#"
+            SELECT max_workers FROM queue.subscriptions
+            WHERE subscription_item_id = $1 AND max_workers IS NOT NULL
+            FOR UPDATE
+            "#,
+        )
+        .bind(&render.subscription_item_id)
+        .fetch_optional(&mut tx)
+        .await
+        .context("RenderRepository::claim_job")?;
+
+        if let Some((max_workers,)) = subscription_limit {
+            let (in_flight,): (i64,) = sqlx::query_as(
+                r#"
+                SELECT COUNT(*)
+                FROM queue.jobs j
+                JOIN queue.queue q ON q.id = j.render_id
+                WHERE q.subscription_item_id = $1
+                "#,
+            )
+            .bind(&render.subscription_item_id)
+            .fetch_one(&mut tx)
+            .await
+            .context("RenderRepository::claim_job")?;
+
+            if in_flight >= i64::from(max_workers) {
+                return Ok(Claim::SubscriptionSaturated);
+            }
+        }
+
+        let claimed = render.clone();
+
+        let requeued: Option<(i32, i32, i32)> = if render.requeued_jobs > 0 {
+            sqlx::query_as(
+                r#"
+                DELETE FROM queue.requeue
+                WHERE (render_id, frame, slice) = (
+                    SELECT render_id, frame, slice FROM queue.requeue
+                    WHERE render_id = $1
+                    ORDER BY frame, slice
+                    LIMIT 1
+                )
+                RETURNING frame, slice, attempt
+                "#,
+            )
+            .bind(id)
+            .fetch_optional(&mut tx)
+            .await
+            .context("RenderRepository::claim_job")?
+        } else {
+            None
+        };
+
+        let job = match requeued {
+            Some((frame, slice, attempt)) => {
+                render.requeued_jobs -= 1;
+                render.job(frame, slice, attempt, worker_id.to_string(), leased_until)
+            }
+            None => match render.get_job(worker_id.to_string(), leased_until) {
+                Some(job) => {
+                    render.advance_pointer();
+                    job
+                }
+                None => return Ok(Claim::Unavailable),
+            },
+        };
+
         sqlx::query(
             r#"
             UPDATE queue.queue
-            SET pointer_frame = $1, pointer_slice = $2
-            WHERE id = $3
+            SET pointer_index = $1, pointer_slice = $2, requeued_jobs = $3, popped_at = now()
+            WHERE id = $4
             "#,
         )
-        .bind(&render.pointer_frame)
+        .bind(&render.pointer_index)
         .bind(&render.pointer_slice)
+        .bind(&render.requeued_jobs)
         .bind(&render.id)
-        .execute(&self.pool)
+        .execute(&mut tx)
         .await
-        .context("RenderRepository::update_pointer")?;
+        .context("RenderRepository::claim_job")?;
 
-        Ok(())
+        if render.status == RenderStatus::Pending {
+            render.transition(RenderStatus::Running, Utc::now())?;
+
+            update_status(&mut tx, &render)
+                .await
+                .context("RenderRepository::claim_job")?;
+        }
+
+        sqlx::query(
+            r#"
+            INSERT INTO queue.jobs (user_id, render_id, frame, slice, attempt, worker_id, leased_until, popped_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, now())
+            "#,
+        )
+        .bind(&user_id_uuid)
+        .bind(&job.render_id)
+        .bind(&job.frame)
+        .bind(&job.slice)
+        .bind(&job.attempt)
+        .bind(&job.worker_id)
+        .bind(&job.leased_until)
+        .execute(&mut tx)
+        .await
+        .context("RenderRepository::claim_job")?;
+
+        write_outbox(&mut tx, &outbox(&claimed, &job))
+            .await
+            .context("RenderRepository::claim_job")?;
+
+        tx.commit().await.context("RenderRepository::claim_job")?;
+
+        Ok(Claim::Claimed(Box::new(claimed), job))
     }
 
-    async fn increment_completed_jobs(&self, id: &str) -> Result<Option<Render>> {
-        let render: Option<Render> = sqlx::query_as(
+    async fn complete_job<F>(
+        &self,
+        id: &str,
+        frame: i32,
+        slice: i32,
+        worker_id: &str,
+        outbox: F,
+    ) -> Result<Option<(Render, Completion)>>
+    where
+        F: FnOnce(&Render, &Completion) -> Vec<Event> + Send,
+    {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .context("RenderRepository::complete_job")?;
+
+        if !lock_render(&mut tx, id)
+            .await
+            .context("RenderRepository::complete_job")?
+        {
+            return Ok(None);
+        }
+
+        // Timed by the database's clock, as the job's pop was, rather than the worker's.
+        let job: Option<(Option<f64>,)> = sqlx::query_as(
+            r#"
+            DELETE FROM queue.jobs
+            WHERE render_id = $1 AND frame = $2 AND slice = $3 AND worker_id = $4
+            RETURNING EXTRACT(EPOCH FROM now() - popped_at)::double precision
+            "#,
+        )
+        .bind(id)
+        .bind(&frame)
+        .bind(&slice)
+        .bind(worker_id)
+        .fetch_optional(&mut tx)
+        .await
+        .context("RenderRepository::complete_job")?;
+
+        let job_seconds = match job {
+            Some((job_seconds,)) => job_seconds,
+            None => return Ok(None),
+        };
+
+        let mut render: Render = sqlx::query_as(
             r#"
             UPDATE queue.queue
             SET completed_jobs = completed_jobs + 1
@@ -109,21 +440,394 @@ impl RenderRepository for PgRenderRepository {
             "#,
         )
         .bind(id)
-        .fetch_optional(&self.pool)
+        .fetch_one(&mut tx)
         .await
-        .context("RenderRepository::increment_completed_jobs")?;
+        .context("RenderRepository::complete_job")?;
 
-        Ok(render)
+        let user_id_uuid: Uuid = render.user_id.parse()?;
+        let file_id_uuid: Uuid = render.file_id.parse()?;
+
+        // Merge jobs and jobs popped before pop times were recorded aren't timed.
+        let seconds = match job_seconds {
+            Some(seconds) if slice < render.slices => Some(seconds.max(0.0)),
+            _ => None,
+        };
+
+        let file_durations: Option<(i32, f64)> = match seconds {
+            Some(seconds) => {
+                render.durations.add(seconds);
+
+                sqlx::query(
+                    "UPDATE queue.queue SET timed_jobs = $1, job_seconds = $2 WHERE id = $3",
+                )
+                .bind(&render.durations.jobs)
+                .bind(&render.durations.seconds)
+                .bind(id)
+                .execute(&mut tx)
+                .await
+                .context("RenderRepository::complete_job")?;
+
+                sqlx::query_as(
+                    r#"
+                    INSERT INTO queue.file_durations
+                        (user_id, file_id, file_version, timed_jobs, job_seconds)
+                    VALUES ($1, $2, $3, 1, $4)
+                    ON CONFLICT (file_id, file_version) DO UPDATE
+                    SET timed_jobs = file_durations.timed_jobs + 1,
+                        job_seconds = file_durations.job_seconds + $4
+                    RETURNING timed_jobs, job_seconds
+                    "#,
+                )
+                .bind(&user_id_uuid)
+                .bind(&file_id_uuid)
+                .bind(&render.file_version)
+                .bind(Durations::per_frame(seconds, render.slices))
+                .fetch_optional(&mut tx)
+                .await
+                .context("RenderRepository::complete_job")?
+            }
+            None => sqlx::query_as(
+                r#"
+                SELECT timed_jobs, job_seconds FROM queue.file_durations
+                WHERE file_id = $1 AND file_version = $2
+                "#,
+            )
+            .bind(&file_id_uuid)
+            .bind(&render.file_version)
+            .fetch_optional(&mut tx)
+            .await
+            .context("RenderRepository::complete_job")?,
+        };
+
+        let result = sqlx::query(
+            r#"
+            INSERT INTO queue.completed_slices (render_id, frame, slice)
+            VALUES ($1, $2, $3)
+            ON CONFLICT DO NOTHING
+            "#,
+        )
+        .bind(id)
+        .bind(&frame)
+        .bind(&slice)
+        .execute(&mut tx)
+        .await
+        .context("RenderRepository::complete_job")?;
+
+        let (completed_slices,): (i64,) = sqlx::query_as(
+            r#"
+            SELECT COUNT(*) FROM queue.completed_slices
+            WHERE render_id = $1 AND frame = $2 AND slice < $3
+            "#,
+        )
+        .bind(id)
+        .bind(&frame)
+        .bind(&render.slices)
+        .fetch_one(&mut tx)
+        .await
+        .context("RenderRepository::complete_job")?;
+
+        // Completing a merge job doesn't complete its frame again.
+        let frame_complete = slice < render.slices
+            && result.rows_affected() > 0
+            && completed_slices == i64::from(render.slices);
+
+        if frame_complete && render.merge && !render.status.is_final() {
+            // A rerun cancels the frame's merge if it is waiting or in flight, so neither should be
+            // found here, and the merge is already counted in total_jobs.
+            let result = sqlx::query(
+                r#"
+                INSERT INTO queue.requeue (render_id, frame, slice, attempt)
+                SELECT $1, $2, $3, 1
+                WHERE NOT EXISTS (
+                    SELECT 1 FROM queue.jobs WHERE render_id = $1 AND frame = $2 AND slice = $3
+                )
+                ON CONFLICT DO NOTHING
+                "#,
+            )
+            .bind(id)
+            .bind(&frame)
+            .bind(render.merge_slice())
+            .execute(&mut tx)
+            .await
+            .context("RenderRepository::complete_job")?;
+
+            if result.rows_affected() > 0 {
+                render.requeued_jobs += 1;
+
+                sqlx::query("UPDATE queue.queue SET requeued_jobs = $1 WHERE id = $2")
+                    .bind(&render.requeued_jobs)
+                    .bind(id)
+                    .execute(&mut tx)
+                    .await
+                    .context("RenderRepository::complete_job")?;
+            }
+        }
+
+        let (in_flight_jobs,): (i64,) =
+            sqlx::query_as("SELECT COUNT(*) FROM queue.jobs WHERE render_id = $1")
+                .bind(id)
+                .fetch_one(&mut tx)
+                .await
+                .context("RenderRepository::complete_job")?;
+
+        // A failed render's remaining jobs are still counted, but don't complete it.
+        if render.is_complete()
+            && render.status.can_transition_to(RenderStatus::Complete)
+            && in_flight_jobs == 0
+        {
+            render.transition(RenderStatus::Complete, Utc::now())?;
+
+            update_status(&mut tx, &render)
+                .await
+                .context("RenderRepository::complete_job")?;
+        }
+
+        let completion = Completion {
+            frame_complete,
+            in_flight_jobs,
+            file_durations: file_durations
+                .map(|(jobs, seconds)| Durations { jobs, seconds })
+                .unwrap_or_default(),
+        };
+
+        write_outbox(&mut tx, &outbox(&render, &completion))
+            .await
+            .context("RenderRepository::complete_job")?;
+
+        tx.commit()
+            .await
+            .context("RenderRepository::complete_job")?;
+
+        Ok(Some((render, completion)))
     }
 
-    async fn delete(&self, id: &str) -> Result<()> {
-        sqlx::query("DELETE FROM queue.queue WHERE id = $1")
+    async fn pause<F>(&self, id: &str, outbox: F) -> Result<Option<Render>>
+    where
+        F: FnOnce(&Render) -> Vec<Event> + Send,
+    {
+        self.transition(
+            id,
+            |r| r.transition(RenderStatus::Paused, Utc::now()),
+            outbox,
+        )
+        .await
+        .context("RenderRepository::pause")
+    }
+
+    async fn resume<F>(&self, id: &str, outbox: F) -> Result<Option<Render>>
+    where
+        F: FnOnce(&Render) -> Vec<Event> + Send,
+    {
+        self.transition(id, |r| r.resume(Utc::now()), outbox)
+            .await
+            .context("RenderRepository::resume")
+    }
+
+    async fn rerun<F>(&self, id: &str, jobs: &[(i32, i32)], outbox: F) -> Result<Rerun>
+    where
+        F: FnOnce(&Render, i32, &[InFlightJob]) -> Vec<Event> + Send,
+    {
+        let mut tx = self.pool.begin().await.context("RenderRepository::rerun")?;
+
+        let render: Option<Render> =
+            sqlx::query_as("SELECT * FROM queue.queue WHERE id = $1 FOR UPDATE")
+                .bind(id)
+                .fetch_optional(&mut tx)
+                .await
+                .context("RenderRepository::rerun")?;
+
+        let mut render = match render {
+            Some(render) => render,
+            None => return Ok(Rerun::NotFound),
+        };
+
+        // Jobs still in flight for a finished render would report back to it as it is requeued,
+        // failing or completing it again.
+        if render.status.is_final() {
+            let in_flight: bool =
+                sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM queue.jobs WHERE render_id = $1)")
+                    .bind(id)
+                    .fetch_one(&mut tx)
+                    .await
+                    .context("RenderRepository::rerun")?;
+
+            if in_flight {
+                return Ok(Rerun::InFlight);
+            }
+        }
+
+        let (frames, slices): (Vec<i32>, Vec<i32>) = jobs.iter().copied().unzip();
+
+        let requeued: Vec<(i32, i32)> = sqlx::query_as(
+            r#"
+            INSERT INTO queue.requeue (render_id, frame, slice, attempt)
+            SELECT $1, job.frame, job.slice, 1
+            FROM UNNEST($2::integer[], $3::integer[]) AS job (frame, slice)
+            WHERE NOT EXISTS (
+                SELECT 1 FROM queue.jobs
+                WHERE render_id = $1 AND frame = job.frame AND slice = job.slice
+            )
+            ON CONFLICT DO NOTHING
+            RETURNING frame, slice
+            "#,
+        )
+        .bind(id)
+        .bind(&frames)
+        .bind(&slices)
+        .fetch_all(&mut tx)
+        .await
+        .context("RenderRepository::rerun")?;
+
+        // The rerun slices have to complete again before their frames do.
+        let (frames, slices): (Vec<i32>, Vec<i32>) = requeued.iter().copied().unzip();
+
+        sqlx::query(
+            r#"
+            DELETE FROM queue.completed_slices
+            WHERE render_id = $1
+            AND (frame, slice) IN (SELECT * FROM UNNEST($2::integer[], $3::integer[]))
+            "#,
+        )
+        .bind(id)
+        .bind(&frames)
+        .bind(&slices)
+        .execute(&mut tx)
+        .await
+        .context("RenderRepository::rerun")?;
+
+        // Merged frames are merged again once their rerun slices complete. A merge that has run is
+        // counted again, and one that is waiting or in flight is canceled, as it would merge the
+        // old slices.
+        let (merges, waiting, canceled) = if render.merge {
+            let merges = sqlx::query(
+                r#"
+                DELETE FROM queue.completed_slices
+                WHERE render_id = $1 AND frame = ANY($2) AND slice = $3
+                "#,
+            )
             .bind(id)
-            .execute(&self.pool)
+            .bind(&frames)
+            .bind(render.merge_slice())
+            .execute(&mut tx)
             .await
-            .context("RenderRepository::delete")?;
+            .context("RenderRepository::rerun")?
+            .rows_affected() as i32;
 
-        Ok(())
+            let waiting = sqlx::query(
+                "DELETE FROM queue.requeue WHERE render_id = $1 AND frame = ANY($2) AND slice = $3",
+            )
+            .bind(id)
+            .bind(&frames)
+            .bind(render.merge_slice())
+            .execute(&mut tx)
+            .await
+            .context("RenderRepository::rerun")?
+            .rows_affected() as i32;
+
+            let canceled: Vec<InFlightJob> = sqlx::query_as(
+                r#"
+                DELETE FROM queue.jobs
+                WHERE render_id = $1 AND frame = ANY($2) AND slice = $3
+                RETURNING *
+                "#,
+            )
+            .bind(id)
+            .bind(&frames)
+            .bind(render.merge_slice())
+            .fetch_all(&mut tx)
+            .await
+            .context("RenderRepository::rerun")?;
+
+            (merges, waiting, canceled)
+        } else {
+            (0, 0, Vec::new())
+        };
+
+        let queued = requeued.len() as i32;
+
+        if queued > 0 {
+            render.requeued_jobs += queued - waiting;
+
+            // A canceled or failed render has jobs counted that will never complete, so the rerun
+            // jobs are all that is left of a finished render.
+            if render.status.is_final() {
+                render.total_jobs = render.completed_jobs + queued + merges;
+            } else {
+                render.total_jobs += queued + merges;
+            }
+
+            sqlx::query("UPDATE queue.queue SET requeued_jobs = $1, total_jobs = $2 WHERE id = $3")
+                .bind(&render.requeued_jobs)
+                .bind(&render.total_jobs)
+                .bind(id)
+                .execute(&mut tx)
+                .await
+                .context("RenderRepository::rerun")?;
+
+            if render.status.is_final() {
+                render.transition(RenderStatus::Pending, Utc::now())?;
+
+                update_status(&mut tx, &render)
+                    .await
+                    .context("RenderRepository::rerun")?;
+            }
+        }
+
+        write_outbox(&mut tx, &outbox(&render, queued, &canceled))
+            .await
+            .context("RenderRepository::rerun")?;
+
+        tx.commit().await.context("RenderRepository::rerun")?;
+
+        Ok(Rerun::Queued(Box::new(render), queued))
+    }
+
+    async fn cancel<F>(&self, id: &str, outbox: F) -> Result<Option<(Render, Vec<InFlightJob>)>>
+    where
+        F: FnOnce(&Render, &[InFlightJob]) -> Vec<Event> + Send,
+    {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .context("RenderRepository::cancel")?;
+
+        let render: Option<Render> =
+            sqlx::query_as("SELECT * FROM queue.queue WHERE id = $1 FOR UPDATE")
+                .bind(id)
+                .fetch_optional(&mut tx)
+                .await
+                .context("RenderRepository::cancel")?;
+
+        let mut render = match render {
+            Some(render) if render.status.can_transition_to(RenderStatus::Canceled) => render,
+            _ => return Ok(None),
+        };
+
+        render.transition(RenderStatus::Canceled, Utc::now())?;
+
+        update_status(&mut tx, &render)
+            .await
+            .context("RenderRepository::cancel")?;
+
+        drain(&mut tx, &mut render)
+            .await
+            .context("RenderRepository::cancel")?;
+
+        let jobs: Vec<InFlightJob> =
+            sqlx::query_as("DELETE FROM queue.jobs WHERE render_id = $1 RETURNING *")
+                .bind(id)
+                .fetch_all(&mut tx)
+                .await
+                .context("RenderRepository::cancel")?;
+
+        write_outbox(&mut tx, &outbox(&render, &jobs))
+            .await
+            .context("RenderRepository::cancel")?;
+
+        tx.commit().await.context("RenderRepository::cancel")?;
+
+        Ok(Some((render, jobs)))
     }
 }
 
@@ -133,30 +837,77 @@ impl FromRow<'_, PgRow> for Render {
         let user_id: Uuid = row.try_get("user_id")?;
         let file_id: Uuid = row.try_get("file_id")?;
         let file_version: i32 = row.try_get("file_version")?;
-        let frame_start: i32 = row.try_get("frame_start")?;
-        let frame_end: i32 = row.try_get("frame_end")?;
-        let step: i32 = row.try_get("step")?;
+        let frames: String = row.try_get("frames")?;
+        let frame_order: String = row.try_get("frame_order")?;
         let slices: i32 = row.try_get("slices")?;
-        let pointer_frame: i32 = row.try_get("pointer_frame")?;
+        let merge: bool = row.try_get("merge")?;
+        let pointer_index: i32 = row.try_get("pointer_index")?;
         let pointer_slice: i32 = row.try_get("pointer_slice")?;
+        let requeued_jobs: i32 = row.try_get("requeued_jobs")?;
         let total_jobs: i32 = row.try_get("total_jobs")?;
         let completed_jobs: i32 = row.try_get("completed_jobs")?;
+        let timed_jobs: i32 = row.try_get("timed_jobs")?;
+        let job_seconds: f64 = row.try_get("job_seconds")?;
         let subscription_item_id: String = row.try_get("subscription_item_id")?;
+        let requirements: Json<Requirements> = row.try_get("requirements")?;
+        let max_attempts: i32 = row.try_get("max_attempts")?;
+        let pool: String = row.try_get("pool")?;
+        let priority: i32 = row.try_get("priority")?;
+        let submitted_at: DateTime<Utc> = row.try_get("submitted_at")?;
+        let popped_at: Option<DateTime<Utc>> = row.try_get("popped_at")?;
+        let status: String = row.try_get("status")?;
+        let started_at: Option<DateTime<Utc>> = row.try_get("started_at")?;
+        let paused_at: Option<DateTime<Utc>> = row.try_get("paused_at")?;
+        let completed_at: Option<DateTime<Utc>> = row.try_get("completed_at")?;
+        let failed_at: Option<DateTime<Utc>> = row.try_get("failed_at")?;
+        let canceled_at: Option<DateTime<Utc>> = row.try_get("canceled_at")?;
 
         Ok(Self {
             id: id,
             user_id: user_id.to_string(),
             file_id: file_id.to_string(),
             file_version,
-            frame_start,
-            frame_end,
-            step,
+            frames: frames
+                .parse()
+                .map_err(|e: anyhow::Error| sqlx::Error::ColumnDecode {
+                    index: "frames".to_string(),
+                    source: e.into(),
+                })?,
+            order: frame_order
+                .parse()
+                .map_err(|e: anyhow::Error| sqlx::Error::ColumnDecode {
+                    index: "frame_order".to_string(),
+                    source: e.into(),
+                })?,
             slices,
-            pointer_frame,
+            merge,
+            pointer_index,
             pointer_slice,
+            requeued_jobs,
             total_jobs,
             completed_jobs,
+            durations: Durations {
+                jobs: timed_jobs,
+                seconds: job_seconds,
+            },
             subscription_item_id,
+            requirements: requirements.0,
+            max_attempts,
+            pool,
+            priority,
+            submitted_at,
+            popped_at,
+            status: status
+                .parse()
+                .map_err(|e: anyhow::Error| sqlx::Error::ColumnDecode {
+                    index: "status".to_string(),
+                    source: e.into(),
+                })?,
+            started_at,
+            paused_at,
+            completed_at,
+            failed_at,
+            canceled_at,
         })
     }
 }
@@ -174,47 +925,580 @@ impl PgJobRepository {
 
 #[async_trait::async_trait]
 impl JobRepository for PgJobRepository {
-    async fn store(&self, job: &Job) -> Result<()> {
-        let user_id_uuid: Uuid = job.user_id.parse()?;
+    async fn load_by_renders(&self, render_ids: &[String]) -> Result<Vec<InFlightJob>> {
+        let jobs: Vec<InFlightJob> = sqlx::query_as(
+            "SELECT * FROM queue.jobs WHERE render_id = ANY($1) ORDER BY render_id, frame, slice",
+        )
+        .bind(render_ids)
+        .fetch_all(&self.pool)
+        .await
+        .context("JobRepository::load_by_renders")?;
+
+        Ok(jobs)
+    }
+
+    async fn count_by_user(&self) -> Result<HashMap<String, i64>> {
+        let counts: Vec<(Uuid, i64)> =
+            sqlx::query_as("SELECT user_id, COUNT(*) FROM queue.jobs GROUP BY user_id")
+                .fetch_all(&self.pool)
+                .await
+                .context("JobRepository::count_by_user")?;
+
+        Ok(counts
+            .into_iter()
+            .map(|(user_id, count)| (user_id.to_string(), count))
+            .collect())
+    }
+
+    async fn release<F>(
+        &self,
+        render_id: &str,
+        frame: i32,
+        slice: i32,
+        worker_id: &str,
+        outbox: F,
+    ) -> Result<Option<(InFlightJob, Release)>>
+    where
+        F: FnOnce(&InFlightJob, Release) -> Vec<Event> + Send,
+    {
+        release_job(&self.pool, render_id, frame, slice, worker_id, None, outbox)
+            .await
+            .context("JobRepository::release")
+    }
+
+    async fn release_expired<F>(
+        &self,
+        now: DateTime<Utc>,
+        outbox: F,
+    ) -> Result<Vec<(InFlightJob, Release)>>
+    where
+        F: Fn(&InFlightJob, Release) -> Vec<Event> + Send + Sync,
+    {
+        let expired: Vec<InFlightJob> =
+            sqlx::query_as("SELECT * FROM queue.jobs WHERE leased_until < $1")
+                .bind(&now)
+                .fetch_all(&self.pool)
+                .await
+                .context("JobRepository::release_expired")?;
+
+        let mut released = Vec::with_capacity(expired.len());
+
+        // Each job is released in its own transaction, so a job that can't be released doesn't
+        // hold back the others.
+        for job in expired {
+            match release_job(
+                &self.pool,
+                &job.render_id,
+                job.frame,
+                job.slice,
+                &job.worker_id,
+                Some(job.leased_until),
+                &outbox,
+            )
+            .await
+            {
+                Ok(Some(job)) => released.push(job),
+                // Completed or released since
+                Ok(None) => {}
+                Err(e) => error!("Failed to release expired job {:?}: {:?}", job, e),
+            }
+        }
+
+        Ok(released)
+    }
+}
+
+// Removes the worker's in-flight job and requeues it, in a transaction of its own. With
+// `leased_until`, only the lease that was seen is released, not one taken out since.
+async fn release_job<F>(
+    pool: &PgPool,
+    render_id: &str,
+    frame: i32,
+    slice: i32,
+    worker_id: &str,
+    leased_until: Option<DateTime<Utc>>,
+    outbox: F,
+) -> Result<Option<(InFlightJob, Release)>>
+where
+    F: FnOnce(&InFlightJob, Release) -> Vec<Event>,
+{
+    let mut tx = pool.begin().await?;
+
+    // A job of a render that no longer exists is still released, and orphaned.
+    lock_render(&mut tx, render_id).await?;
+
+    let job: Option<InFlightJob> = sqlx::query_as(
+        r#"
+        DELETE FROM queue.jobs
+        WHERE render_id = $1 AND frame = $2 AND slice = $3 AND worker_id = $4
+        AND ($5::timestamptz IS NULL OR leased_until = $5)
+        RETURNING *
+        "#,
+    )
+    .bind(render_id)
+    .bind(&frame)
+    .bind(&slice)
+    .bind(worker_id)
+    .bind(&leased_until)
+    .fetch_optional(&mut tx)
+    .await?;
+
+    let job = match job {
+        Some(job) => job,
+        None => return Ok(None),
+    };
+
+    let release = requeue(&mut tx, &job).await?;
+
+    write_outbox(&mut tx, &outbox(&job, release)).await?;
+
+    tx.commit().await?;
+
+    Ok(Some((job, release)))
+}
+
+// Locks the render, returning false if it doesn't exist. Every transaction that touches both a
+// render and its jobs locks the render first, so two of them can't deadlock on each other's locks.
+async fn lock_render(tx: &mut Transaction<'_, Postgres>, id: &str) -> Result<bool> {
+    let render: Option<(String,)> =
+        sqlx::query_as("SELECT id FROM queue.queue WHERE id = $1 FOR UPDATE")
+            .bind(id)
+            .fetch_optional(&mut *tx)
+            .await?;
+
+    Ok(render.is_some())
+}
+
+// Hands the job's (frame, slice) back to its render for another attempt, or fails the render if
+// it has used up its attempts.
+async fn requeue(tx: &mut Transaction<'_, Postgres>, job: &InFlightJob) -> Result<Release> {
+    let render: Option<Render> =
+        sqlx::query_as("SELECT * FROM queue.queue WHERE id = $1 FOR UPDATE")
+            .bind(&job.render_id)
+            .fetch_optional(&mut *tx)
+            .await?;
+
+    let mut render = match render {
+        Some(render) if !render.status.is_final() => render,
+        _ => return Ok(Release::Orphaned),
+    };
+
+    if render.max_attempts > job.attempt {
+        sqlx::query("UPDATE queue.queue SET requeued_jobs = requeued_jobs + 1 WHERE id = $1")
+            .bind(&job.render_id)
+            .execute(&mut *tx)
+            .await?;
 
         sqlx::query(
             r#"
-            INSERT INTO queue.jobs (user_id, render_id, frame, slice, worker_id)
-            VALUES ($1, $2, $3, $4, $5)
+            INSERT INTO queue.requeue (render_id, frame, slice, attempt)
+            VALUES ($1, $2, $3, $4)
             "#,
         )
-        .bind(&user_id_uuid)
         .bind(&job.render_id)
         .bind(&job.frame)
         .bind(&job.slice)
-        .bind(&job.worker_id)
+        .bind(job.attempt + 1)
+        .execute(&mut *tx)
+        .await?;
+
+        return Ok(Release::Requeued);
+    }
+
+    render.transition(RenderStatus::Failed, Utc::now())?;
+    update_status(tx, &render).await?;
+    drain(tx, &mut render).await?;
+
+    Ok(Release::RenderFailed)
+}
+
+// Stops a render that ended early (canceled or failed) handing out more jobs, by draining its
+// pointer and dropping its requeued jobs. A rerun then only brings back the frames it asks for.
+async fn drain(tx: &mut Transaction<'_, Postgres>, render: &mut Render) -> Result<()> {
+    render.drain_pointer();
+    render.requeued_jobs = 0;
+
+    sqlx::query(
+        r#"
+        UPDATE queue.queue
+        SET pointer_index = $1, pointer_slice = $2, requeued_jobs = $3
+        WHERE id = $4
+        "#,
+    )
+    .bind(&render.pointer_index)
+    .bind(&render.pointer_slice)
+    .bind(&render.requeued_jobs)
+    .bind(&render.id)
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query("DELETE FROM queue.requeue WHERE render_id = $1")
+        .bind(&render.id)
+        .execute(&mut *tx)
+        .await?;
+
+    Ok(())
+}
+
+async fn update_status(tx: &mut Transaction<'_, Postgres>, render: &Render) -> Result<()> {
+    sqlx::query(
+        r#"
+        UPDATE queue.queue
+        SET status = $1, started_at = $2, paused_at = $3, completed_at = $4, failed_at = $5, canceled_at = $6
+        WHERE id = $7
+        "#,
+    )
+    .bind(render.status.as_str())
+    .bind(&render.started_at)
+    .bind(&render.paused_at)
+    .bind(&render.completed_at)
+    .bind(&render.failed_at)
+    .bind(&render.canceled_at)
+    .bind(&render.id)
+    .execute(&mut *tx)
+    .await?;
+
+    Ok(())
+}
+
+async fn write_outbox(tx: &mut Transaction<'_, Postgres>, events: &[Event]) -> Result<()> {
+    for event in events {
+        sqlx::query("INSERT INTO queue.outbox (event) VALUES ($1)")
+            .bind(Json(event))
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    Ok(())
+}
+
+impl FromRow<'_, PgRow> for InFlightJob {
+    fn from_row(row: &PgRow) -> Result<Self, sqlx::Error> {
+        let user_id: Uuid = row.try_get("user_id")?;
+        let render_id: String = row.try_get("render_id")?;
+        let frame: i32 = row.try_get("frame")?;
+        let slice: i32 = row.try_get("slice")?;
+        let attempt: i32 = row.try_get("attempt")?;
+        let worker_id: String = row.try_get("worker_id")?;
+        let leased_until: DateTime<Utc> = row.try_get("leased_until")?;
+        let popped_at: Option<DateTime<Utc>> = row.try_get("popped_at")?;
+
+        Ok(Self {
+            user_id: user_id.to_string(),
+            render_id,
+            frame,
+            slice,
+            attempt,
+            worker_id,
+            leased_until,
+            popped_at,
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PgUserRepository {
+    pool: PgPool,
+}
+
+impl PgUserRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl UserRepository for PgUserRepository {
+    async fn load_all(&self) -> Result<Vec<User>> {
+        let users: Vec<User> = sqlx::query_as("SELECT * FROM queue.users")
+            .fetch_all(&self.pool)
+            .await
+            .context("UserRepository::load_all")?;
+
+        Ok(users)
+    }
+
+    async fn load_subscriptions(&self) -> Result<Vec<Subscription>> {
+        let subscriptions: Vec<Subscription> = sqlx::query_as("SELECT * FROM queue.subscriptions")
+            .fetch_all(&self.pool)
+            .await
+            .context("UserRepository::load_subscriptions")?;
+
+        Ok(subscriptions)
+    }
+}
+
+impl FromRow<'_, PgRow> for User {
+    fn from_row(row: &PgRow) -> Result<Self, sqlx::Error> {
+        let id: Uuid = row.try_get("user_id")?;
+        let weight: i32 = row.try_get("weight")?;
+        let max_workers: Option<i32> = row.try_get("max_workers")?;
+
+        Ok(Self {
+            id: id.to_string(),
+            weight,
+            max_workers,
+        })
+    }
+}
+
+impl FromRow<'_, PgRow> for Subscription {
+    fn from_row(row: &PgRow) -> Result<Self, sqlx::Error> {
+        let item_id: String = row.try_get("subscription_item_id")?;
+        let max_workers: Option<i32> = row.try_get("max_workers")?;
+        let priority: i32 = row.try_get("priority")?;
+
+        Ok(Self {
+            item_id,
+            max_workers,
+            priority,
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PgWorkerRepository {
+    pool: PgPool,
+}
+
+impl PgWorkerRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl WorkerRepository for PgWorkerRepository {
+    async fn seen(&self, worker_id: &str, at: DateTime<Utc>) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO queue.workers (worker_id, first_seen_at, last_seen_at)
+            VALUES ($1, $2, $2)
+            ON CONFLICT (worker_id) DO UPDATE
+            SET last_seen_at = GREATEST(workers.last_seen_at, excluded.last_seen_at), lost_at = NULL
+            "#,
+        )
+        .bind(worker_id)
+        .bind(&at)
         .execute(&self.pool)
         .await
-        .context("JobRepository::store")?;
+        .context("WorkerRepository::seen")?;
 
         Ok(())
     }
 
-    async fn delete(&self, render_id: String, frame: i32, slice: i32) -> Result<()> {
-        sqlx::query("DELETE FROM queue.jobs WHERE render_id = $1 AND frame = $2 AND slice = $3")
-            .bind(&render_id)
-            .bind(&frame)
-            .bind(&slice)
+    async fn heartbeat(
+        &self,
+        worker_id: &str,
+        at: DateTime<Utc>,
+        leased_until: DateTime<Utc>,
+    ) -> Result<()> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .context("WorkerRepository::heartbeat")?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO queue.workers (worker_id, first_seen_at, last_seen_at, heartbeat_at)
+            VALUES ($1, $2, $2, $2)
+            ON CONFLICT (worker_id) DO UPDATE
+            SET last_seen_at = GREATEST(workers.last_seen_at, excluded.last_seen_at),
+                heartbeat_at = GREATEST(workers.heartbeat_at, excluded.heartbeat_at),
+                lost_at = NULL
+            "#,
+        )
+        .bind(worker_id)
+        .bind(&at)
+        .execute(&mut tx)
+        .await
+        .context("WorkerRepository::heartbeat")?;
+
+        sqlx::query(
+            r#"
+            UPDATE queue.jobs
+            SET leased_until = GREATEST(leased_until, $2)
+            WHERE worker_id = $1
+            "#,
+        )
+        .bind(worker_id)
+        .bind(&leased_until)
+        .execute(&mut tx)
+        .await
+        .context("WorkerRepository::heartbeat")?;
+
+        tx.commit().await.context("WorkerRepository::heartbeat")?;
+
+        Ok(())
+    }
+
+    async fn release_lost<R, L>(
+        &self,
+        deadline: DateTime<Utc>,
+        released: R,
+        lost: L,
+    ) -> Result<Vec<(String, Vec<(InFlightJob, Release)>)>>
+    where
+        R: Fn(&InFlightJob, Release) -> Vec<Event> + Send + Sync,
+        L: Fn(&str, &[(InFlightJob, Release)]) -> Vec<Event> + Send + Sync,
+    {
+        let workers: Vec<(String,)> = sqlx::query_as(
+            r#"
+            SELECT worker_id FROM queue.workers
+            WHERE lost_at IS NULL AND heartbeat_at IS NOT NULL AND last_seen_at < $1
+            "#,
+        )
+        .bind(&deadline)
+        .fetch_all(&self.pool)
+        .await
+        .context("WorkerRepository::release_lost")?;
+
+        let mut lost_workers = Vec::with_capacity(workers.len());
+
+        for (worker_id,) in workers {
+            let jobs: Vec<InFlightJob> =
+                sqlx::query_as("SELECT * FROM queue.jobs WHERE worker_id = $1")
+                    .bind(&worker_id)
+                    .fetch_all(&self.pool)
+                    .await
+                    .context("WorkerRepository::release_lost")?;
+
+            let mut jobs_released = Vec::with_capacity(jobs.len());
+
+            // As with expired leases, each job is released in its own transaction. One that fails
+            // stays in flight until its lease expires.
+            for job in jobs {
+                match release_job(
+                    &self.pool,
+                    &job.render_id,
+                    job.frame,
+                    job.slice,
+                    &job.worker_id,
+                    None,
+                    &released,
+                )
+                .await
+                {
+                    Ok(Some(job)) => jobs_released.push(job),
+                    // Completed or released since
+                    Ok(None) => {}
+                    Err(e) => error!("Failed to release job of lost worker {:?}: {:?}", job, e),
+                }
+            }
+
+            let mut tx = self
+                .pool
+                .begin()
+                .await
+                .context("WorkerRepository::release_lost")?;
+
+            // The worker may have been seen again while its jobs were released.
+            let result = sqlx::query(
+                r#"
+                UPDATE queue.workers
+                SET lost_at = now()
+                WHERE worker_id = $1 AND lost_at IS NULL AND last_seen_at < $2
+                "#,
+            )
+            .bind(&worker_id)
+            .bind(&deadline)
+            .execute(&mut tx)
+            .await
+            .context("WorkerRepository::release_lost")?;
+
+            if result.rows_affected() == 0 {
+                continue;
+            }
+
+            write_outbox(&mut tx, &lost(&worker_id, &jobs_released))
+                .await
+                .context("WorkerRepository::release_lost")?;
+
+            tx.commit()
+                .await
+                .context("WorkerRepository::release_lost")?;
+
+            lost_workers.push((worker_id, jobs_released));
+        }
+
+        Ok(lost_workers)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PgOutboxRepository {
+    pool: PgPool,
+}
+
+impl PgOutboxRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl OutboxRepository for PgOutboxRepository {
+    async fn store(&self, events: &[Event]) -> Result<()> {
+        let mut tx = self.pool.begin().await.context("OutboxRepository::store")?;
+
+        write_outbox(&mut tx, events)
+            .await
+            .context("OutboxRepository::store")?;
+
+        tx.commit().await.context("OutboxRepository::store")?;
+
+        Ok(())
+    }
+
+    async fn claim(&self, limit: i64, lease: Duration) -> Result<Vec<(i64, Event)>> {
+        let claimed_until = Utc::now() + lease;
+
+        let mut events: Vec<(i64, Json<Event>)> = sqlx::query_as(
+            r#"
+            UPDATE queue.outbox
+            SET claimed_until = $2
+            WHERE id IN (
+                SELECT id FROM queue.outbox
+                WHERE claimed_until IS NULL OR claimed_until < now()
+                ORDER BY id
+                LIMIT $1
+                FOR UPDATE SKIP LOCKED
+            )
+            RETURNING id, event
+            "#,
+        )
+        .bind(&limit)
+        .bind(&claimed_until)
+        .fetch_all(&self.pool)
+        .await
+        .context("OutboxRepository::claim")?;
+
+        events.sort_by_key(|(id, _)| *id);
+
+        Ok(events
+            .into_iter()
+            .map(|(id, Json(event))| (id, event))
+            .collect())
+    }
+
+    async fn delete(&self, ids: &[i64]) -> Result<()> {
+        sqlx::query("DELETE FROM queue.outbox WHERE id = ANY($1)")
+            .bind(ids)
             .execute(&self.pool)
             .await
-            .context("JobRepository::delete")?;
+            .context("OutboxRepository::delete")?;
 
         Ok(())
     }
 
-    async fn count(&self, render_id: String) -> Result<i64> {
-        let count: i64 = sqlx::query("SELECT COUNT(*) FROM queue.jobs WHERE render_id = $1")
-            .bind(&render_id)
-            .map(|row: sqlx::postgres::PgRow| row.get(0))
-            .fetch_one(&self.pool)
+    async fn release(&self, ids: &[i64]) -> Result<()> {
+        sqlx::query("UPDATE queue.outbox SET claimed_until = NULL WHERE id = ANY($1)")
+            .bind(ids)
+            .execute(&self.pool)
             .await
-            .context("JobRepository::count")?;
+            .context("OutboxRepository::release")?;
 
-        Ok(count)
+        Ok(())
     }
 }
@@ -1,7 +1,13 @@
 use anyhow::Result;
-use api::service::QueueServiceImpl;
+use api::metrics::{self, Metrics};
+use api::relay::OutboxRelay;
+use api::service::{QueueOptions, QueueServiceImpl};
+use chrono::Duration;
 use clap::Parser;
-use infrastructure::postgres::{PgJobRepository, PgRenderRepository};
+use domain::scaling::ScalePolicy;
+use infrastructure::postgres::{
+    PgJobRepository, PgOutboxRepository, PgRenderRepository, PgUserRepository, PgWorkerRepository,
+};
 use libcubr::event::event::EventTransport;
 use libcubr::event::nats::NATSEventTransport;
 use libcubr::rpc::nats::NATSRPC;
@@ -25,10 +31,43 @@ async fn main() -> Result<()> {
 
     let event = NATSEventTransport::new(nc.clone(), "queue".to_string());
     let render = PgRenderRepository::new(pool.clone());
-    let job = PgJobRepository::new(pool);
+    let job = PgJobRepository::new(pool.clone());
+    let user = PgUserRepository::new(pool.clone());
+    let worker = PgWorkerRepository::new(pool.clone());
+    let outbox = PgOutboxRepository::new(pool);
     let rpc = NATSRPC::new(nc, "queue".to_string());
+    let metrics = Metrics::new()?;
 
-    let service = QueueServiceImpl::new(render, job, event.clone());
+    let service = QueueServiceImpl::new(
+        render,
+        job,
+        user,
+        worker,
+        config.load_balancer,
+        outbox.clone(),
+        QueueOptions {
+            lease: Duration::seconds(config.job_lease_seconds),
+            max_attempts: config.max_job_attempts,
+            default_pool: config.default_pool,
+            fair_share: config.fair_share,
+            worker_timeout: Duration::seconds(config.worker_heartbeat_timeout_seconds),
+            scaling: ScalePolicy {
+                min: config.scale_target_min,
+                max: config.scale_target_max,
+                max_per_render: config.scale_max_workers_per_render,
+                scale_down_delay: Duration::seconds(config.scale_down_delay_seconds),
+                drain_time: config.scale_drain_seconds.map(Duration::seconds),
+            },
+        },
+        metrics.clone(),
+    );
+    let reaper = service.clone();
+    let gauges = service.clone();
+    let relay = OutboxRelay::new(
+        outbox,
+        event.clone(),
+        Duration::seconds(config.outbox_claim_seconds),
+    );
 
     tokio::select! {
         _ = event.listen(service.clone()) => {
@@ -37,6 +76,18 @@ async fn main() -> Result<()> {
         _ = rpc.listen(service) => {
             error!("RPC listener exited");
         }
+        _ = reaper.run_reaper(std::time::Duration::from_secs(config.lease_reaper_interval_seconds)) => {
+            error!("Reaper exited");
+        }
+        _ = relay.run(std::time::Duration::from_millis(config.outbox_relay_interval_millis)) => {
+            error!("Outbox relay exited");
+        }
+        result = metrics::serve(config.metrics_port, metrics, move || {
+            let gauges = gauges.clone();
+            async move { gauges.update_queue_metrics().await }
+        }) => {
+            error!("Metrics server exited: {:?}", result);
+        }
     }
 
     info!("Exiting");